@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{DynamicImage, Rgb, RgbImage};
+use secret::{decrypt_image, hide_image, normalize_image, HideImageOptions, ImageBits};
+
+const SIZES: [u32; 3] = [512, 2048, 4096];
+
+fn generate_image(size: u32, seed: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+        Rgb([
+            (x + seed) as u8,
+            (y * 3 + seed) as u8,
+            ((x ^ y) + seed) as u8,
+        ])
+    }))
+}
+
+// Throughput is counted in pixels, so Criterion reports it in Melem/s, which
+// reads as megapixels per second.
+fn bench_transform(
+    c: &mut Criterion,
+    name: &str,
+    transform: impl Fn(&DynamicImage, &DynamicImage) -> DynamicImage,
+) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+
+    for size in SIZES {
+        let source = generate_image(size, 0);
+        let secret = generate_image(size, 97);
+
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| transform(&source, &secret))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_hide_image(c: &mut Criterion) {
+    bench_transform(c, "hide_image", |source, secret| {
+        hide_image(source, secret, &HideImageOptions::default()).unwrap()
+    });
+}
+
+fn bench_decrypt_image(c: &mut Criterion) {
+    bench_transform(c, "decrypt_image", |source, _| {
+        decrypt_image(source, ImageBits::default(), 0).unwrap()
+    });
+}
+
+fn bench_normalize_image(c: &mut Criterion) {
+    bench_transform(c, "normalize_image", |source, _| normalize_image(source));
+}
+
+criterion_group!(
+    benches,
+    bench_hide_image,
+    bench_decrypt_image,
+    bench_normalize_image
+);
+criterion_main!(benches);
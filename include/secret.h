@@ -0,0 +1,39 @@
+/* Declarations for the C interface built with the `ffi` feature, matching
+ * src/ffi.rs. */
+#ifndef SECRET_H
+#define SECRET_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SECRET_OK 0
+#define SECRET_ERROR_NULL_POINTER 1
+#define SECRET_ERROR_IMAGE 2
+#define SECRET_ERROR_CAPACITY 3
+#define SECRET_ERROR_INVALID_PAYLOAD 4
+#define SECRET_ERROR_PANIC 5
+
+/* Hides `payload` in the encoded `carrier` image and writes a newly allocated
+ * PNG to `out_stego`/`out_stego_len`. Nothing is written to the outputs on
+ * error. */
+int secret_hide(const uint8_t *carrier, size_t carrier_len, const uint8_t *payload,
+                size_t payload_len, uint8_t **out_stego, size_t *out_stego_len);
+
+/* Extracts the payload hidden in the encoded `stego` image into a newly
+ * allocated buffer written to `out_payload`/`out_payload_len`. */
+int secret_extract(const uint8_t *stego, size_t stego_len, uint8_t **out_payload,
+                   size_t *out_payload_len);
+
+/* Releases a buffer returned by `secret_hide` or `secret_extract`, along with
+ * its length. Passing a null pointer does nothing. */
+void secret_free(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
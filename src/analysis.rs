@@ -0,0 +1,80 @@
+use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+
+use crate::SecretError;
+
+// Peak signal-to-noise ratio in decibels over the RGB channels, which is
+// infinite for identical images.
+pub fn psnr(original: &DynamicImage, modified: &DynamicImage) -> Result<f64, SecretError> {
+    if original.dimensions() != modified.dimensions() {
+        return Err(SecretError::DimensionMismatch {
+            expected: original.dimensions(),
+            actual: modified.dimensions(),
+        });
+    }
+
+    let original_buffer = original.to_rgb8();
+    let modified_buffer = modified.to_rgb8();
+
+    let squared_error: f64 = original_buffer
+        .iter()
+        .zip(modified_buffer.iter())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    let mean_squared_error = squared_error / original_buffer.len() as f64;
+
+    Ok(10.0 * (255.0 * 255.0 / mean_squared_error).log10())
+}
+
+pub fn diff_images(
+    original: &DynamicImage,
+    stego: &DynamicImage,
+) -> Result<(DynamicImage, usize), SecretError> {
+    if original.dimensions() != stego.dimensions() {
+        return Err(SecretError::DimensionMismatch {
+            expected: original.dimensions(),
+            actual: stego.dimensions(),
+        });
+    }
+
+    let original_buffer = original.to_rgba16();
+    let stego_buffer = stego.to_rgba16();
+
+    let mut changed_pixels = 0;
+    let diff_buffer = GrayImage::from_fn(original.width(), original.height(), |x, y| {
+        if original_buffer.get_pixel(x, y) == stego_buffer.get_pixel(x, y) {
+            Luma([0])
+        } else {
+            changed_pixels += 1;
+            Luma([255])
+        }
+    });
+
+    Ok((DynamicImage::ImageLuma8(diff_buffer), changed_pixels))
+}
+
+// Widening an 8-bit sample to 16 bits repeats it in both bytes, so the low
+// eight bit planes are the same at either depth. White pixels have the bit set.
+// Channels are counted in RGBA order.
+pub fn extract_bit_plane(
+    image: &DynamicImage,
+    channel: usize,
+    bit: u8,
+) -> Result<DynamicImage, SecretError> {
+    if channel >= 4 {
+        return Err(SecretError::InvalidChannel(channel));
+    }
+
+    let buffer = image.to_rgba16();
+
+    let plane_buffer = GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let sample = buffer.get_pixel(x, y)[channel];
+
+        if sample.checked_shr(bit as u32).unwrap_or(0) & 1 == 1 {
+            Luma([255])
+        } else {
+            Luma([0])
+        }
+    });
+
+    Ok(DynamicImage::ImageLuma8(plane_buffer))
+}
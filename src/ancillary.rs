@@ -0,0 +1,113 @@
+use crate::SecretError;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+// These chunks describe the color space, physical size, text and EXIF data of
+// an image, none of which depend on the low bits of its pixels. They are all
+// allowed right after IHDR.
+const COPIED_CHUNKS: [&[u8; 4]; 10] = [
+    b"iCCP", b"sRGB", b"gAMA", b"cHRM", b"pHYs", b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME",
+];
+
+struct Chunk<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+}
+
+impl Chunk<'_> {
+    fn write_to(&self, bytes: &mut Vec<u8>) {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.kind);
+        hasher.update(self.data);
+
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.kind);
+        bytes.extend_from_slice(self.data);
+        bytes.extend_from_slice(&hasher.finalize().to_be_bytes());
+    }
+}
+
+fn png_chunks(png: &[u8]) -> Option<Vec<Chunk<'_>>> {
+    let mut rest = png.strip_prefix(PNG_SIGNATURE)?;
+    let mut chunks = Vec::new();
+
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+        let kind = rest.get(4..8)?.try_into().unwrap();
+        let data = rest.get(8..8usize.checked_add(len)?)?;
+
+        chunks.push(Chunk { kind, data });
+        rest = rest.get(12 + len..)?;
+    }
+
+    Some(chunks)
+}
+
+// JPEG carriers keep their EXIF data in an APP1 segment, which becomes an eXIf
+// chunk in the PNG output.
+fn jpeg_chunks(jpeg: &[u8]) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let Some(mut rest) = jpeg.strip_prefix(&[0xFF, 0xD8]) else {
+        return chunks;
+    };
+
+    while let [0xFF, marker, ..] = *rest {
+        // Start of scan is followed by the image data, with no more metadata.
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+
+        let Some(len) = rest.get(2..4) else {
+            break;
+        };
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let Some(segment) = rest.get(4..2 + len.max(2)) else {
+            break;
+        };
+
+        if marker == 0xE1 {
+            if let Some(exif) = segment.strip_prefix(EXIF_HEADER) {
+                chunks.push(Chunk {
+                    kind: *b"eXIf",
+                    data: exif,
+                });
+            }
+        }
+
+        rest = &rest[2 + len.max(2)..];
+    }
+
+    chunks
+}
+
+// Inserts the color space, text and EXIF metadata of the original `carrier`
+// file, a PNG or a JPEG, into the freshly encoded `png`.
+pub fn copy_metadata(carrier: &[u8], png: &[u8]) -> Result<Vec<u8>, SecretError> {
+    let output_chunks = png_chunks(png).ok_or(SecretError::InvalidPng)?;
+
+    let carrier_chunks = if carrier.starts_with(PNG_SIGNATURE) {
+        png_chunks(carrier).ok_or(SecretError::InvalidPng)?
+    } else {
+        jpeg_chunks(carrier)
+    };
+
+    let copied_chunks = carrier_chunks.iter().filter(|chunk| {
+        COPIED_CHUNKS.contains(&&chunk.kind)
+            && !output_chunks.iter().any(|output| output.kind == chunk.kind)
+    });
+
+    let mut bytes = PNG_SIGNATURE.to_vec();
+    let mut output_chunks = output_chunks.iter();
+
+    match output_chunks.next() {
+        Some(header) if &header.kind == b"IHDR" => header.write_to(&mut bytes),
+        _ => return Err(SecretError::InvalidPng),
+    }
+
+    for chunk in copied_chunks.chain(output_chunks) {
+        chunk.write_to(&mut bytes);
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,146 @@
+use std::{collections::HashSet, io::Cursor};
+
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Encoder, EncodingError, Repeat};
+use image::{
+    codecs::gif::GifDecoder, error, AnimationDecoder, DynamicImage, Frame, ImageError, ImageFormat,
+    Rgb, RgbImage, RgbaImage,
+};
+use log::debug;
+
+use crate::{hide_bytes_across_images, join_bytes_from_images, SecretError};
+
+// The default placement changes at most one bit of one channel, so a frame
+// reduced to this many colors still fits an exact 256-color palette after
+// embedding, next to the entry for transparent pixels, and is encoded without
+// further quantization.
+const FRAME_COLORS: usize = 127;
+const QUANTIZER_SPEED: i32 = 10;
+const TRANSPARENT: [u8; 4] = [0; 4];
+
+fn decode_frames(gif: &[u8]) -> Result<Vec<Frame>, SecretError> {
+    let decoder = GifDecoder::new(Cursor::new(gif))?;
+    Ok(decoder.into_frames().collect_frames()?)
+}
+
+fn gif_error(error: EncodingError) -> SecretError {
+    match error {
+        EncodingError::Io(error) => SecretError::Io(error),
+        error => SecretError::Image(ImageError::Encoding(error::EncodingError::new(
+            ImageFormat::Gif.into(),
+            error,
+        ))),
+    }
+}
+
+// GIF pixels are either opaque or transparent, and transparent ones all share
+// one palette entry that would swallow the hidden bits, so they are cleared
+// and only the opaque ones have their colors reduced.
+fn quantize_frame(buffer: &RgbaImage) -> RgbaImage {
+    let mut buffer = buffer.clone();
+    for pixel in buffer.pixels_mut() {
+        pixel.0 = if pixel[3] == 0 {
+            TRANSPARENT
+        } else {
+            [pixel[0], pixel[1], pixel[2], u8::MAX]
+        };
+    }
+
+    let opaque: Vec<u8> = buffer
+        .pixels()
+        .filter(|pixel| pixel[3] != 0)
+        .flat_map(|pixel| pixel.0)
+        .collect();
+    let colors: HashSet<_> = opaque.chunks_exact(4).collect();
+    if colors.len() <= FRAME_COLORS {
+        return buffer;
+    }
+
+    let quantizer = NeuQuant::new(QUANTIZER_SPEED, FRAME_COLORS, &opaque);
+    for pixel in buffer.chunks_exact_mut(4) {
+        if pixel[3] != 0 {
+            quantizer.map_pixel(pixel);
+            pixel[3] = u8::MAX;
+        }
+    }
+
+    buffer
+}
+
+// The opaque pixels of a frame in a single row, which is what the payload is
+// spread over, so that transparent pixels never hold any of it.
+fn opaque_pixels(buffer: &RgbaImage) -> DynamicImage {
+    let pixels: Vec<u8> = buffer
+        .pixels()
+        .filter(|pixel| pixel[3] != 0)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let width = (pixels.len() / 3) as u32;
+
+    DynamicImage::ImageRgb8(RgbImage::from_raw(width, 1, pixels).unwrap())
+}
+
+fn restore_opaque_pixels(buffer: &mut RgbaImage, opaque: &DynamicImage) {
+    let opaque = opaque.to_rgb8();
+    let frame_pixels = buffer.pixels_mut().filter(|pixel| pixel[3] != 0);
+
+    for (pixel, &Rgb([red, green, blue])) in frame_pixels.zip(opaque.pixels()) {
+        pixel.0 = [red, green, blue, u8::MAX];
+    }
+}
+
+pub fn hide_bytes_in_gif(gif: &[u8], payload: &[u8]) -> Result<Vec<u8>, SecretError> {
+    let frames = decode_frames(gif)?;
+    let mut buffers: Vec<RgbaImage> = frames
+        .iter()
+        .map(|frame| quantize_frame(frame.buffer()))
+        .collect();
+    let carriers: Vec<DynamicImage> = buffers.iter().map(opaque_pixels).collect();
+
+    debug!(
+        "Embedding {} bytes across up to {} frames",
+        payload.len(),
+        carriers.len()
+    );
+
+    let images = hide_bytes_across_images(&carriers, payload)?;
+    for (buffer, image) in buffers.iter_mut().zip(&images) {
+        if let Some(image) = image {
+            restore_opaque_pixels(buffer, image);
+        }
+    }
+
+    // Decoded frames are already composited onto the whole canvas. Clearing
+    // each one before the next keeps the transparent pixels transparent when
+    // the GIF is decoded again, instead of showing the frame before.
+    let (width, height) = buffers[0].dimensions();
+    let mut stego = Vec::new();
+    {
+        let mut encoder =
+            Encoder::new(&mut stego, width as u16, height as u16, &[]).map_err(gif_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+
+        for (buffer, frame) in buffers.iter_mut().zip(&frames) {
+            let (delay_numerator, delay_denominator) = frame.delay().numer_denom_ms();
+            let mut gif_frame =
+                gif::Frame::from_rgba_speed(width as u16, height as u16, buffer, QUANTIZER_SPEED);
+            gif_frame.delay = (delay_numerator / delay_denominator / 10)
+                .try_into()
+                .unwrap_or(u16::MAX);
+            gif_frame.dispose = DisposalMethod::Background;
+
+            encoder.write_frame(&gif_frame).map_err(gif_error)?;
+        }
+    }
+
+    Ok(stego)
+}
+
+pub fn extract_bytes_from_gif(gif: &[u8]) -> Result<Vec<u8>, SecretError> {
+    let frames: Vec<DynamicImage> = decode_frames(gif)?
+        .iter()
+        .map(|frame| opaque_pixels(frame.buffer()))
+        .collect();
+
+    join_bytes_from_images(&frames)
+}
@@ -0,0 +1,82 @@
+use std::{fmt, str::FromStr};
+
+use crate::{SecretError, MAX_IMAGE_BITS};
+
+// The number of low bits of the red, green and blue samples that carry a
+// secret image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageBits(pub [u8; 3]);
+
+impl ImageBits {
+    pub const fn uniform(bits: u8) -> Self {
+        Self([bits; 3])
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        self.0.iter().all(|&bits| bits == self.0[0])
+    }
+
+    pub fn channel(&self, channel: usize) -> u8 {
+        self.0[channel]
+    }
+
+    // The bits of a whole pixel.
+    pub fn total(&self) -> u8 {
+        self.0.iter().sum()
+    }
+
+    pub fn doubled(&self) -> Self {
+        Self(self.0.map(|bits| 2 * bits))
+    }
+
+    // Every channel needs between 1 and `MAX_IMAGE_BITS` bits, which must fit
+    // in the sample above `offset`.
+    pub fn check(&self, offset: u8) -> Result<(), SecretError> {
+        match self.0.iter().find(|&&bits| {
+            !(1..=MAX_IMAGE_BITS).contains(&bits) || bits > 8u8.saturating_sub(offset)
+        }) {
+            Some(&bits) => Err(SecretError::InvalidBits(bits)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for ImageBits {
+    fn default() -> Self {
+        Self::uniform(2)
+    }
+}
+
+impl fmt::Display for ImageBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            [bits, ..] if self.is_uniform() => write!(f, "{}", bits),
+            [red, green, blue] => write!(f, "{},{},{}", red, green, blue),
+        }
+    }
+}
+
+impl FromStr for ImageBits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| error.to_string())?;
+
+        if let Some(bits) = values
+            .iter()
+            .find(|&&bits| bits == 0 || bits > MAX_IMAGE_BITS)
+        {
+            return Err(format!("{} is not in 1..={}", bits, MAX_IMAGE_BITS));
+        }
+
+        match values[..] {
+            [bits] => Ok(Self::uniform(bits)),
+            [red, green, blue] => Ok(Self([red, green, blue])),
+            _ => Err(String::from("expected one value or r,g,b")),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fmt, str::FromStr};
+
+const CHANNEL_NAMES: [char; 4] = ['r', 'g', 'b', 'a'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Channels(u8);
+
+impl Channels {
+    pub const RED: Self = Self(0b001);
+    pub const GREEN: Self = Self(0b010);
+    pub const BLUE: Self = Self(0b100);
+    pub const RGB: Self = Self(0b111);
+    pub const ALPHA: Self = Self(0b1000);
+
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        (bits != 0 && bits & !(Self::RGB.0 | Self::ALPHA.0) == 0).then_some(Self(bits))
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(&self, channel: usize) -> bool {
+        self.0 & (1 << channel) != 0
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.0 & Self::ALPHA.0 != 0
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Self::RED
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (channel, name) in CHANNEL_NAMES.iter().enumerate() {
+            if self.contains(channel) {
+                write!(f, "{}", name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Channels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bits = 0;
+
+        for name in s.chars() {
+            match CHANNEL_NAMES.iter().position(|&channel| channel == name) {
+                Some(channel) => bits |= 1 << channel,
+                None => return Err(format!("unknown channel '{}'", name)),
+            }
+        }
+
+        Self::from_bits(bits).ok_or_else(|| String::from("expected at least one of r, g, b, a"))
+    }
+}
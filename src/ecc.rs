@@ -0,0 +1,219 @@
+use image::{ColorType, DynamicImage, GenericImageView};
+use log::debug;
+use reed_solomon::{Decoder, Encoder};
+
+use crate::{
+    embed_stream, extract_stream, length_header,
+    mode::{extract_with_mode, find_mode_header, mode_header_samples, write_mode_header},
+    progress, stream_capacity, EmbedOptions, EmbedReport, ModeHeader, PayloadKind, Placement,
+    SecretError,
+};
+
+// The header holds the payload length and the parity length of the data
+// blocks, and is protected by its own fixed amount of parity.
+const HEADER_LEN: usize = 5;
+const HEADER_PARITY: usize = 16;
+const BLOCK_LEN: usize = 255;
+
+pub const DEFAULT_PARITY: u8 = 16;
+
+fn encode(payload: &[u8], payload_len: u32, parity: u8) -> Vec<u8> {
+    let mut header = payload_len.to_be_bytes().to_vec();
+    header.push(parity);
+
+    let mut stream = Encoder::new(HEADER_PARITY).encode(&header).to_vec();
+
+    let encoder = Encoder::new(parity as usize);
+    for block in payload.chunks(BLOCK_LEN - parity as usize) {
+        stream.extend_from_slice(&encoder.encode(block));
+    }
+
+    stream
+}
+
+// The mode header of an ECC payload carries parity of its own, so it takes up
+// more samples than an unprotected one.
+pub(crate) fn ecc_placement_for(
+    options: &EmbedOptions,
+    width: u32,
+    height: u32,
+    color: ColorType,
+) -> Result<Placement, SecretError> {
+    let mut placement = options.placement_for(width, height, color)?;
+
+    if options.header {
+        placement.skip = mode_header_samples(options.region.is_some(), true);
+    }
+
+    Ok(placement)
+}
+
+fn ecc_placement(image: &DynamicImage, options: &EmbedOptions) -> Result<Placement, SecretError> {
+    let (width, height) = image.dimensions();
+    ecc_placement_for(options, width, height, image.color())
+}
+
+// Every full block carries `BLOCK_LEN - parity` payload bytes, and a trailing
+// partial block whatever remains after its parity.
+pub fn ecc_payload_capacity(
+    image: &DynamicImage,
+    parity: u8,
+    options: &EmbedOptions,
+) -> Result<usize, SecretError> {
+    if parity == 0 || parity as usize >= BLOCK_LEN {
+        return Err(SecretError::InvalidParity(parity));
+    }
+
+    let placement = ecc_placement(image, options)?;
+
+    Ok(ecc_capacity(
+        options.fill_limit(stream_capacity(&placement))?,
+        parity,
+    ))
+}
+
+pub(crate) fn ecc_capacity(stream_capacity: usize, parity: u8) -> usize {
+    let capacity = stream_capacity.saturating_sub(HEADER_LEN + HEADER_PARITY);
+    let parity = parity as usize;
+
+    capacity / BLOCK_LEN * (BLOCK_LEN - parity) + (capacity % BLOCK_LEN).saturating_sub(parity)
+}
+
+pub fn hide_bytes_with_ecc(
+    image: &DynamicImage,
+    payload: &[u8],
+    parity: u8,
+    options: &EmbedOptions,
+) -> Result<DynamicImage, SecretError> {
+    hide_bytes_with_ecc_report(image, payload, parity, options)
+        .map(|(hidden_image, _)| hidden_image)
+}
+
+pub fn hide_bytes_with_ecc_report(
+    image: &DynamicImage,
+    payload: &[u8],
+    parity: u8,
+    options: &EmbedOptions,
+) -> Result<(DynamicImage, EmbedReport), SecretError> {
+    if parity == 0 || parity as usize >= BLOCK_LEN {
+        return Err(SecretError::InvalidParity(parity));
+    }
+
+    let payload_len = length_header(payload)?;
+    let placement = ecc_placement(image, options)?;
+    let stream = encode(payload, payload_len, parity);
+    let capacity = stream_capacity(&placement);
+
+    debug!(
+        "Embedding {} bytes with {} bytes of parity into a capacity of {} bytes",
+        payload.len(),
+        stream.len() - payload.len(),
+        capacity
+    );
+
+    if stream.len() > capacity {
+        return Err(SecretError::InsufficientCapacity {
+            needed: stream.len(),
+            available: capacity,
+        });
+    }
+
+    let allowed = options.fill_limit(capacity)?;
+    if stream.len() > allowed {
+        return Err(SecretError::FillFractionExceeded {
+            needed: stream.len(),
+            allowed,
+            capacity,
+        });
+    }
+
+    let report = EmbedReport::new(
+        &placement,
+        payload.len(),
+        stream.len(),
+        ecc_capacity(capacity, parity),
+    );
+    let hidden_image = embed_stream(image, &placement, stream.into_iter());
+
+    if !options.header {
+        return Ok((hidden_image, report));
+    }
+
+    let header = ModeHeader {
+        bits: placement.bits,
+        channels: options.channels,
+        region: options.region,
+        parity,
+        compressed: false,
+        encrypted: false,
+        kind: PayloadKind::Bytes,
+        payload_len,
+    };
+
+    Ok((write_mode_header(hidden_image, &header)?, report))
+}
+
+// Like `extract_bytes_from_image`, the options of a mode header win.
+pub fn extract_bytes_with_ecc(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<Vec<u8>, SecretError> {
+    match find_mode_header(image, options.region) {
+        Some(header) => extract_with_mode(image, &header),
+        None => extract_ecc_bytes(image, options),
+    }
+}
+
+pub(crate) fn extract_ecc_bytes(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<Vec<u8>, SecretError> {
+    let placement = ecc_placement(image, options)?;
+    let mut stream = extract_stream(image, placement);
+
+    let header: Vec<u8> = stream.by_ref().take(HEADER_LEN + HEADER_PARITY).collect();
+    if header.len() < HEADER_LEN + HEADER_PARITY {
+        return Err(SecretError::Uncorrectable);
+    }
+
+    let header = Decoder::new(HEADER_PARITY)
+        .correct(&header, None)
+        .map_err(|_| SecretError::Uncorrectable)?;
+    let header = header.data();
+
+    let payload_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let parity = header[4] as usize;
+
+    if parity == 0 || parity >= BLOCK_LEN {
+        return Err(SecretError::Uncorrectable);
+    }
+
+    debug!(
+        "Extracting {} bytes with {} parity bytes per block",
+        payload_len, parity
+    );
+
+    let decoder = Decoder::new(parity);
+    let mut payload = Vec::new();
+    progress::expect(payload_len as u64);
+
+    while payload.len() < payload_len {
+        let data_len = (BLOCK_LEN - parity).min(payload_len - payload.len());
+
+        let block: Vec<u8> = stream.by_ref().take(data_len + parity).collect();
+        if block.len() < data_len + parity {
+            return Err(SecretError::LengthMismatch {
+                expected: payload_len,
+                actual: payload.len(),
+            });
+        }
+
+        let block = decoder
+            .correct(&block, None)
+            .map_err(|_| SecretError::Uncorrectable)?;
+        payload.extend_from_slice(block.data());
+        progress::advance(data_len as u64);
+    }
+
+    Ok(payload)
+}
@@ -0,0 +1,277 @@
+use std::{error::Error, fmt, io, string::FromUtf8Error};
+
+use image::{ImageError, ImageFormat};
+use serde_json::{json, Value};
+
+use crate::{PayloadKind, Region};
+
+#[derive(Debug)]
+pub enum SecretError {
+    InsufficientCapacity {
+        needed: usize,
+        available: usize,
+    },
+    DeclaredLengthExceedsCapacity {
+        declared: usize,
+        capacity: usize,
+    },
+    FillFractionExceeded {
+        needed: usize,
+        allowed: usize,
+        capacity: usize,
+    },
+    PayloadTooLarge(usize),
+    InvalidChunk,
+    MissingChunk(u16),
+    LengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    InvalidParity(u8),
+    InvalidBits(u8),
+    InvalidChannel(usize),
+    InvalidFillFraction(f64),
+    NoAlphaChannel,
+    DimensionMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    DimensionsTooLarge {
+        width: u32,
+        height: u32,
+        max_pixels: u64,
+    },
+    RegionOutOfBounds {
+        region: Region,
+        width: u32,
+        height: u32,
+    },
+    Uncorrectable,
+    UnsupportedMode,
+    InvalidMetadata(serde_json::Error),
+    NotMetadata(PayloadKind),
+    InvalidText(FromUtf8Error),
+    InvalidTag(String),
+    InvalidTagTable,
+    TagNotFound(String),
+    InvalidPng,
+    LossyFormat(ImageFormat),
+    Image(ImageError),
+    Io(io::Error),
+}
+
+impl SecretError {
+    // The name of the variant, which scripts can match on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InsufficientCapacity { .. } => "InsufficientCapacity",
+            Self::DeclaredLengthExceedsCapacity { .. } => "DeclaredLengthExceedsCapacity",
+            Self::FillFractionExceeded { .. } => "FillFractionExceeded",
+            Self::PayloadTooLarge(_) => "PayloadTooLarge",
+            Self::InvalidChunk => "InvalidChunk",
+            Self::MissingChunk(_) => "MissingChunk",
+            Self::LengthMismatch { .. } => "LengthMismatch",
+            Self::InvalidParity(_) => "InvalidParity",
+            Self::InvalidBits(_) => "InvalidBits",
+            Self::InvalidChannel(_) => "InvalidChannel",
+            Self::InvalidFillFraction(_) => "InvalidFillFraction",
+            Self::NoAlphaChannel => "NoAlphaChannel",
+            Self::DimensionMismatch { .. } => "DimensionMismatch",
+            Self::DimensionsTooLarge { .. } => "DimensionsTooLarge",
+            Self::RegionOutOfBounds { .. } => "RegionOutOfBounds",
+            Self::Uncorrectable => "Uncorrectable",
+            Self::UnsupportedMode => "UnsupportedMode",
+            Self::InvalidMetadata(_) => "InvalidMetadata",
+            Self::NotMetadata(_) => "NotMetadata",
+            Self::InvalidText(_) => "InvalidText",
+            Self::InvalidTag(_) => "InvalidTag",
+            Self::InvalidTagTable => "InvalidTagTable",
+            Self::TagNotFound(_) => "TagNotFound",
+            Self::InvalidPng => "InvalidPng",
+            Self::LossyFormat(_) => "LossyFormat",
+            Self::Image(_) => "Image",
+            Self::Io(_) => "Io",
+        }
+    }
+
+    // The kind, the fields of the variant and the message as a JSON object.
+    pub fn to_json(&self) -> Value {
+        let mut value = match self {
+            Self::InsufficientCapacity { needed, available } => {
+                json!({ "needed": needed, "available": available })
+            }
+            Self::DeclaredLengthExceedsCapacity { declared, capacity } => {
+                json!({ "declared": declared, "capacity": capacity })
+            }
+            Self::FillFractionExceeded {
+                needed,
+                allowed,
+                capacity,
+            } => json!({ "needed": needed, "allowed": allowed, "capacity": capacity }),
+            Self::PayloadTooLarge(len) => json!({ "len": len }),
+            Self::MissingChunk(index) => json!({ "index": index }),
+            Self::LengthMismatch { expected, actual } => {
+                json!({ "expected": expected, "actual": actual })
+            }
+            Self::InvalidParity(parity) => json!({ "parity": parity }),
+            Self::InvalidBits(bits) => json!({ "bits": bits }),
+            Self::InvalidChannel(channel) => json!({ "channel": channel }),
+            Self::InvalidFillFraction(fraction) => json!({ "fraction": fraction }),
+            Self::DimensionMismatch { expected, actual } => json!({
+                "expected": [expected.0, expected.1],
+                "actual": [actual.0, actual.1],
+            }),
+            Self::DimensionsTooLarge {
+                width,
+                height,
+                max_pixels,
+            } => json!({ "width": width, "height": height, "max_pixels": max_pixels }),
+            Self::RegionOutOfBounds {
+                region,
+                width,
+                height,
+            } => json!({ "region": region.to_string(), "width": width, "height": height }),
+            Self::NotMetadata(kind) => json!({ "payload": kind.to_string() }),
+            Self::InvalidTag(tag) | Self::TagNotFound(tag) => json!({ "tag": tag }),
+            Self::LossyFormat(format) => json!({ "format": format!("{:?}", format) }),
+            _ => json!({}),
+        };
+
+        value["kind"] = json!(self.kind());
+        value["message"] = json!(self.to_string());
+        value
+    }
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientCapacity { needed, available } => write!(
+                f,
+                "insufficient space in the image: {} bytes needed, {} available",
+                needed, available
+            ),
+            Self::DeclaredLengthExceedsCapacity { declared, capacity } => write!(
+                f,
+                "the image declares a {} byte payload but can only hold {} bytes",
+                declared, capacity
+            ),
+            Self::FillFractionExceeded {
+                needed,
+                allowed,
+                capacity,
+            } => write!(
+                f,
+                "the payload needs {} bytes, but the fill fraction only allows {} of the {} available",
+                needed, allowed, capacity
+            ),
+            Self::PayloadTooLarge(len) => write!(
+                f,
+                "a {} byte payload is too large for the 32-bit length header",
+                len
+            ),
+            Self::InvalidChunk => write!(f, "the image does not contain a valid chunk"),
+            Self::MissingChunk(index) => write!(f, "chunk {} is missing", index),
+            Self::LengthMismatch { expected, actual } => write!(
+                f,
+                "the payload should be {} bytes long, but {} bytes were recovered",
+                expected, actual
+            ),
+            Self::InvalidParity(parity) => {
+                write!(f, "{} is not a valid number of parity bytes", parity)
+            }
+            Self::RegionOutOfBounds {
+                region,
+                width,
+                height,
+            } => write!(
+                f,
+                "the region {} does not fit in the {}x{} image",
+                region, width, height
+            ),
+            Self::InvalidBits(bits) => write!(f, "{} is not a valid number of bits", bits),
+            Self::InvalidChannel(channel) => {
+                write!(f, "{} is not a valid channel, it must be below 4", channel)
+            }
+            Self::InvalidFillFraction(fraction) => write!(
+                f,
+                "{} is not a valid fill fraction, it must be above 0 and at most 1",
+                fraction
+            ),
+            Self::NoAlphaChannel => write!(f, "the image has no alpha channel"),
+            Self::DimensionMismatch { expected, actual } => write!(
+                f,
+                "expected a {}x{} image, but got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Self::DimensionsTooLarge {
+                width,
+                height,
+                max_pixels,
+            } => write!(
+                f,
+                "a {}x{} image is larger than the limit of {} pixels",
+                width, height, max_pixels
+            ),
+            Self::Uncorrectable => write!(f, "the payload is too damaged to be corrected"),
+            Self::UnsupportedMode => write!(
+                f,
+                "the payload is compressed or encrypted, which this version cannot read"
+            ),
+            Self::InvalidMetadata(error) => write!(f, "the metadata is not valid: {}", error),
+            Self::NotMetadata(kind) => write!(f, "the payload holds {}, not metadata", kind),
+            Self::InvalidText(error) => write!(f, "the payload is not UTF-8 text: {}", error),
+            Self::InvalidTag(tag) => write!(
+                f,
+                "\"{}\" is not a valid tag, it must be 1 to 255 bytes long",
+                tag
+            ),
+            Self::InvalidTagTable => write!(f, "the table of tagged payloads is malformed"),
+            Self::TagNotFound(tag) => write!(f, "no payload is tagged \"{}\"", tag),
+            Self::InvalidPng => write!(f, "the PNG file is malformed"),
+            Self::LossyFormat(format) => write!(
+                f,
+                "{:?} cannot be relied on to keep hidden data, use PNG, BMP or WebP",
+                format
+            ),
+            Self::Image(error) => write!(f, "{}", error),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SecretError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidMetadata(error) => Some(error),
+            Self::InvalidText(error) => Some(error),
+            Self::Image(error) => Some(error),
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for SecretError {
+    fn from(error: ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl From<serde_json::Error> for SecretError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::InvalidMetadata(error)
+    }
+}
+
+impl From<FromUtf8Error> for SecretError {
+    fn from(error: FromUtf8Error) -> Self {
+        Self::InvalidText(error)
+    }
+}
+
+impl From<io::Error> for SecretError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
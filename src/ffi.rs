@@ -0,0 +1,125 @@
+use std::{
+    os::raw::c_int,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use image::ImageFormat;
+
+use crate::{extract_payload_bytes, hide_payload_bytes, SecretError};
+
+pub const SECRET_OK: c_int = 0;
+pub const SECRET_ERROR_NULL_POINTER: c_int = 1;
+pub const SECRET_ERROR_IMAGE: c_int = 2;
+pub const SECRET_ERROR_CAPACITY: c_int = 3;
+pub const SECRET_ERROR_INVALID_PAYLOAD: c_int = 4;
+pub const SECRET_ERROR_PANIC: c_int = 5;
+
+fn error_code(error: &SecretError) -> c_int {
+    match error {
+        SecretError::Image(_) | SecretError::Io(_) => SECRET_ERROR_IMAGE,
+        SecretError::InsufficientCapacity { .. } => SECRET_ERROR_CAPACITY,
+        _ => SECRET_ERROR_INVALID_PAYLOAD,
+    }
+}
+
+unsafe fn input<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        (len == 0).then_some(&[])
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+// Ownership of the bytes moves to the caller, who must hand them back to
+// `secret_free` together with the length.
+unsafe fn output(bytes: Vec<u8>, out_data: *mut *mut u8, out_len: *mut usize) {
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+
+    *out_len = bytes.len();
+    *out_data = bytes as *mut u8;
+}
+
+fn run(f: impl FnOnce() -> Result<c_int, SecretError>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(code)) => code,
+        Ok(Err(error)) => error_code(&error),
+        Err(_) => SECRET_ERROR_PANIC,
+    }
+}
+
+/// Hides `payload` in the encoded `carrier` image and writes a newly allocated
+/// PNG to `out_stego`/`out_stego_len`. The input buffers are only borrowed for
+/// the duration of the call, and nothing is written to the outputs on error.
+///
+/// # Safety
+///
+/// `carrier` and `payload` must point to `carrier_len` and `payload_len`
+/// readable bytes, and `out_stego` and `out_stego_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn secret_hide(
+    carrier: *const u8,
+    carrier_len: usize,
+    payload: *const u8,
+    payload_len: usize,
+    out_stego: *mut *mut u8,
+    out_stego_len: *mut usize,
+) -> c_int {
+    let (Some(carrier), Some(payload)) = (input(carrier, carrier_len), input(payload, payload_len))
+    else {
+        return SECRET_ERROR_NULL_POINTER;
+    };
+    if out_stego.is_null() || out_stego_len.is_null() {
+        return SECRET_ERROR_NULL_POINTER;
+    }
+
+    run(|| {
+        let stego = hide_payload_bytes(carrier, payload, ImageFormat::Png)?;
+        output(stego, out_stego, out_stego_len);
+
+        Ok(SECRET_OK)
+    })
+}
+
+/// Extracts the payload hidden in the encoded `stego` image into a newly
+/// allocated buffer written to `out_payload`/`out_payload_len`.
+///
+/// # Safety
+///
+/// `stego` must point to `stego_len` readable bytes, and `out_payload` and
+/// `out_payload_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn secret_extract(
+    stego: *const u8,
+    stego_len: usize,
+    out_payload: *mut *mut u8,
+    out_payload_len: *mut usize,
+) -> c_int {
+    let Some(stego) = input(stego, stego_len) else {
+        return SECRET_ERROR_NULL_POINTER;
+    };
+    if out_payload.is_null() || out_payload_len.is_null() {
+        return SECRET_ERROR_NULL_POINTER;
+    }
+
+    run(|| {
+        let payload = extract_payload_bytes(stego)?;
+        output(payload, out_payload, out_payload_len);
+
+        Ok(SECRET_OK)
+    })
+}
+
+/// Releases a buffer returned by `secret_hide` or `secret_extract`. Passing a
+/// null pointer does nothing.
+///
+/// # Safety
+///
+/// `data` and `len` must come from a single successful call to one of the
+/// functions above, and the buffer must not be used or freed again.
+#[no_mangle]
+pub unsafe extern "C" fn secret_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
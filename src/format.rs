@@ -0,0 +1,28 @@
+// Lossless WebP stores its pixels in a VP8L chunk, while lossy WebP uses VP8
+// chunks, either alone or inside an extended VP8X file.
+pub fn is_lossy_webp(bytes: &[u8]) -> bool {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return false;
+    }
+
+    let mut rest = &bytes[12..];
+
+    while rest.len() >= 8 {
+        let kind = &rest[..4];
+        let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+
+        match kind {
+            b"VP8 " => return true,
+            b"VP8L" => return false,
+            _ => {}
+        }
+
+        // Chunks are padded to an even length.
+        let Some(next) = rest.get(8 + len + len % 2..) else {
+            break;
+        };
+        rest = next;
+    }
+
+    false
+}
@@ -0,0 +1,204 @@
+use image::{DynamicImage, RgbImage};
+
+use crate::ImageBits;
+
+// The header is stored MSB-first in the lowest secret bit of each channel of
+// the first pixels, and starts with a magic number so that images hidden
+// without one are told apart. The top bit of the bits byte marks an
+// interleaved secret, and the next one a fourth byte holding the green and
+// blue bits when they differ from the red ones. A third flag marks a secret
+// that was resized or expanded to fit, and is followed by its shape: the kind
+// byte, the original width and height and, for a resized secret, the size of
+// the resized image inside the padding, all as big-endian u32. A CRC-32 of
+// everything before it ends the header, so that the magic number alone does
+// not make an image without a header pass for one.
+const IMAGE_MAGIC: u16 = 0x5345;
+const IMAGE_HEADER_LEN: usize = 3;
+const IMAGE_CRC_LEN: usize = 4;
+const IMAGE_HEADER_MAX_LEN: usize = IMAGE_HEADER_LEN + 1 + 1 + 16 + IMAGE_CRC_LEN;
+const INTERLEAVED: u8 = 0x80;
+const PER_CHANNEL: u8 = 0x40;
+const SHAPE: u8 = 0x20;
+
+const SHAPE_CROPPED: u8 = 0;
+const SHAPE_RESIZED: u8 = 1;
+
+// How the secret was brought to the size of the carrier, so that it can be
+// brought back to its own size once decrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretShape {
+    // Padded, tiled or cropped, so the secret starts at the top left.
+    Cropped {
+        width: u32,
+        height: u32,
+    },
+    // Resized to `inner_width` by `inner_height`, which is smaller than the
+    // carrier when the aspect ratio was kept.
+    Resized {
+        width: u32,
+        height: u32,
+        inner_width: u32,
+        inner_height: u32,
+    },
+}
+
+impl SecretShape {
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            SecretShape::Cropped { width, height } | SecretShape::Resized { width, height, .. } => {
+                (width, height)
+            }
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let (kind, values) = match self {
+            SecretShape::Cropped { width, height } => (SHAPE_CROPPED, vec![width, height]),
+            SecretShape::Resized {
+                width,
+                height,
+                inner_width,
+                inner_height,
+            } => (
+                SHAPE_RESIZED,
+                vec![width, height, inner_width, inner_height],
+            ),
+        };
+
+        let mut bytes = vec![kind];
+        for value in values {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let u32_at = |index: usize| {
+            let field = bytes.get(index..index + 4)?;
+            Some(u32::from_be_bytes(field.try_into().unwrap())).filter(|&value| value > 0)
+        };
+
+        match *bytes.first()? {
+            SHAPE_CROPPED => Some(SecretShape::Cropped {
+                width: u32_at(1)?,
+                height: u32_at(5)?,
+            }),
+            SHAPE_RESIZED => Some(SecretShape::Resized {
+                width: u32_at(1)?,
+                height: u32_at(5)?,
+                inner_width: u32_at(9)?,
+                inner_height: u32_at(13)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub bits: ImageBits,
+    pub interleaved: bool,
+    pub shape: Option<SecretShape>,
+}
+
+impl ImageHeader {
+    fn to_bytes(self) -> Vec<u8> {
+        let [red, green, blue] = self.bits.0;
+
+        let mut flags = 0;
+        if self.interleaved {
+            flags |= INTERLEAVED;
+        }
+        if !self.bits.is_uniform() {
+            flags |= PER_CHANNEL;
+        }
+        if self.shape.is_some() {
+            flags |= SHAPE;
+        }
+
+        let mut bytes = IMAGE_MAGIC.to_be_bytes().to_vec();
+        bytes.push(flags | red);
+        if !self.bits.is_uniform() {
+            bytes.push(green << 4 | blue);
+        }
+        if let Some(shape) = self.shape {
+            bytes.extend_from_slice(&shape.to_bytes());
+        }
+        bytes.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+        bytes
+    }
+
+    // The number of samples the header takes up.
+    pub(crate) fn samples(self) -> usize {
+        self.to_bytes().len() * 8
+    }
+
+    fn from_bytes(bytes: &[u8], offset: u8) -> Option<Self> {
+        if u16::from_be_bytes([bytes[0], bytes[1]]) != IMAGE_MAGIC {
+            return None;
+        }
+
+        let red = bytes[2] & !(INTERLEAVED | PER_CHANNEL | SHAPE);
+        let interleaved = bytes[2] & INTERLEAVED != 0;
+        let mut len = IMAGE_HEADER_LEN;
+
+        let bits = if bytes[2] & PER_CHANNEL != 0 {
+            let channels = *bytes.get(len)?;
+            len += 1;
+            ImageBits([red, channels >> 4, channels & 0x0F])
+        } else {
+            ImageBits::uniform(red)
+        };
+
+        let shape = if bytes[2] & SHAPE != 0 {
+            let shape = SecretShape::from_bytes(bytes.get(len..)?)?;
+            len += shape.to_bytes().len();
+            Some(shape)
+        } else {
+            None
+        };
+
+        let checksum = u32::from_be_bytes(bytes.get(len..len + IMAGE_CRC_LEN)?.try_into().unwrap());
+        if crc32fast::hash(&bytes[..len]) != checksum {
+            return None;
+        }
+
+        bits.check(offset).ok().map(|()| Self {
+            bits,
+            interleaved,
+            shape,
+        })
+    }
+}
+
+pub(crate) fn write_image_header(buffer: &mut RgbImage, header: ImageHeader, offset: u8) {
+    let bits = header
+        .to_bytes()
+        .into_iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+
+    for (sample, bit) in buffer.iter_mut().zip(bits) {
+        *sample = (*sample & !(1 << offset)) | (bit << offset);
+    }
+}
+
+pub fn read_image_header(hidden_image: &DynamicImage, offset: u8) -> Option<ImageHeader> {
+    let buffer = hidden_image.to_rgb8();
+
+    let bits: Vec<u8> = buffer
+        .iter()
+        .take(IMAGE_HEADER_MAX_LEN * 8)
+        .map(|sample| (sample >> offset) & 1)
+        .collect();
+
+    if bits.len() < IMAGE_HEADER_LEN * 8 {
+        return None;
+    }
+
+    let bytes: Vec<u8> = bits
+        .chunks_exact(8)
+        .map(|byte| byte.iter().fold(0, |acc, bit| acc << 1 | bit))
+        .collect();
+
+    ImageHeader::from_bytes(&bytes, offset)
+}
@@ -0,0 +1,1465 @@
+mod analysis;
+mod ancillary;
+mod animation;
+mod bits;
+mod channels;
+mod ecc;
+mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod format;
+mod header;
+mod limits;
+mod linear;
+mod memory;
+mod meta;
+mod mode;
+mod progress;
+mod region;
+mod split;
+mod stego;
+mod stream;
+mod strip;
+mod tags;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use image::{
+    imageops::{self, FilterType},
+    ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageResult, Rgb,
+};
+use log::{debug, warn};
+
+use header::write_image_header;
+use mode::{extract_with_mode, find_mode_header, mode_header_samples, write_mode_header};
+
+pub use analysis::{diff_images, extract_bit_plane, psnr};
+pub use ancillary::copy_metadata;
+pub use animation::{extract_bytes_from_gif, hide_bytes_in_gif};
+pub use bits::ImageBits;
+pub use channels::Channels;
+pub use ecc::{
+    ecc_payload_capacity, extract_bytes_with_ecc, hide_bytes_with_ecc, hide_bytes_with_ecc_report,
+    DEFAULT_PARITY,
+};
+pub use error::SecretError;
+pub use format::is_lossy_webp;
+pub use header::{read_image_header, ImageHeader, SecretShape};
+pub use limits::{check_dimensions, load_image, open_image, DEFAULT_MAX_PIXELS};
+pub use linear::resize_image_linear;
+pub use memory::{extract_payload_bytes, extract_text_bytes, hide_payload_bytes, hide_text_bytes};
+pub use meta::{extract_meta_from_image, hide_meta_in_image, Metadata};
+pub use mode::{read_mode_header, ModeHeader, PayloadKind};
+pub use progress::{progress, reset_progress, Progress};
+pub use region::Region;
+pub use split::{hide_bytes_across_images, join_bytes_from_images};
+pub use stego::{EccStego, LsbStego, Steganographer};
+pub use stream::{declared_payload, read_declared_payload, DeclaredPayload};
+pub use strip::strip_lsb;
+pub use tags::{
+    append_tagged_payload, extract_tagged_payload, hide_tagged_payload, read_tagged_payloads,
+    TaggedPayloads,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExpandMode {
+    #[default]
+    Pad,
+    Tile,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HideImageOptions {
+    pub resize: bool,
+    pub expand: bool,
+    pub expand_mode: ExpandMode,
+    pub keep_aspect: bool,
+    pub filter: FilterType,
+    pub bits: ImageBits,
+    pub offset: u8,
+    pub header: bool,
+    // The largest hidden image, in pixels, that resizing or expanding may
+    // produce.
+    pub max_pixels: u64,
+    // Splits every secret sample over two horizontally adjacent carrier
+    // pixels, `bits` in each, so the secret keeps twice as many bits but may
+    // only be half as wide as the carrier.
+    pub interleave: bool,
+    // Resizes in linear light, which keeps the brightness of downscaled
+    // details, instead of on the gamma-encoded samples.
+    pub linear_resize: bool,
+}
+
+impl Default for HideImageOptions {
+    fn default() -> Self {
+        Self {
+            resize: false,
+            expand: false,
+            expand_mode: ExpandMode::Pad,
+            keep_aspect: false,
+            filter: FilterType::Lanczos3,
+            bits: ImageBits::default(),
+            offset: 0,
+            header: false,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            interleave: false,
+            linear_resize: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFit {
+    ResizeSource,
+    ExpandSource,
+    ResizeSecret,
+    ExpandSecret,
+    // Crops or pads the secret to the source size.
+    FitSecret,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FitPlan {
+    pub fit: ImageFit,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Decides how `hide_image` brings both images to the same size, and what size
+// the hidden image ends up with, without touching any pixels. An interleaved
+// secret needs a carrier twice as wide as itself.
+pub fn plan_image_fit(
+    source_dimensions: (u32, u32),
+    secret_dimensions: (u32, u32),
+    options: &HideImageOptions,
+) -> FitPlan {
+    let secret_dimensions = if options.interleave {
+        (secret_dimensions.0.saturating_mul(2), secret_dimensions.1)
+    } else {
+        secret_dimensions
+    };
+
+    let fit = if source_dimensions < secret_dimensions {
+        if options.resize {
+            ImageFit::ResizeSource
+        } else if options.expand {
+            ImageFit::ExpandSource
+        } else {
+            ImageFit::FitSecret
+        }
+    } else if options.resize {
+        ImageFit::ResizeSecret
+    } else if options.expand {
+        ImageFit::ExpandSecret
+    } else {
+        ImageFit::FitSecret
+    };
+
+    let (width, height) = match fit {
+        ImageFit::ResizeSource | ImageFit::ExpandSource => secret_dimensions,
+        _ => source_dimensions,
+    };
+
+    FitPlan { fit, width, height }
+}
+
+// The shape `hide_image` records in the image header when the secret has to
+// be resized, expanded, cropped or padded to fit the carrier, and `None` when
+// the secret is hidden at its own size.
+pub fn secret_shape(
+    source_dimensions: (u32, u32),
+    secret_dimensions: (u32, u32),
+    options: &HideImageOptions,
+) -> Option<SecretShape> {
+    let plan = plan_image_fit(source_dimensions, secret_dimensions, options);
+    let (slot_width, slot_height) = secret_slots(plan.width, plan.height, options.interleave);
+    let (width, height) = secret_dimensions;
+
+    match plan.fit {
+        ImageFit::ResizeSource | ImageFit::ExpandSource => None,
+        _ if (slot_width, slot_height) == secret_dimensions => None,
+        ImageFit::ResizeSecret => {
+            let (inner_width, inner_height) = if options.keep_aspect {
+                fit_dimensions(width, height, slot_width, slot_height)
+            } else {
+                (slot_width, slot_height)
+            };
+
+            Some(SecretShape::Resized {
+                width,
+                height,
+                inner_width,
+                inner_height,
+            })
+        }
+        ImageFit::ExpandSecret | ImageFit::FitSecret => {
+            Some(SecretShape::Cropped { width, height })
+        }
+    }
+}
+
+fn resize_with_options(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    options: &HideImageOptions,
+) -> DynamicImage {
+    if options.linear_resize {
+        resize_image_linear(image, width, height, options.keep_aspect, options.filter)
+    } else {
+        resize_image(image, width, height, options.keep_aspect, options.filter)
+    }
+}
+
+// The source brought to the size of the hidden image, which is what the
+// hidden image differs from.
+fn fit_source(
+    source_image: &DynamicImage,
+    plan: FitPlan,
+    options: &HideImageOptions,
+) -> DynamicImage {
+    let (source_width, source_height) = source_image.dimensions();
+
+    match plan.fit {
+        ImageFit::ResizeSource => {
+            debug!(
+                "Resizing source from {}x{} to {}x{}",
+                source_width, source_height, plan.width, plan.height
+            );
+            resize_with_options(source_image, plan.width, plan.height, options)
+        }
+        ImageFit::ExpandSource => {
+            debug!(
+                "Expanding source from {}x{} to {}x{}",
+                source_width, source_height, plan.width, plan.height
+            );
+            expand_with_mode(source_image, plan.width, plan.height, options.expand_mode)
+        }
+        _ => source_image.clone(),
+    }
+}
+
+pub fn hide_image(
+    source_image: &DynamicImage,
+    secret_image: &DynamicImage,
+    options: &HideImageOptions,
+) -> Result<DynamicImage, SecretError> {
+    let HideImageOptions {
+        expand_mode,
+        bits,
+        offset,
+        header,
+        max_pixels,
+        interleave,
+        ..
+    } = *options;
+    bits.check(offset)?;
+
+    let (secret_width, secret_height) = secret_image.dimensions();
+
+    let plan = plan_image_fit(
+        source_image.dimensions(),
+        secret_image.dimensions(),
+        options,
+    );
+    check_dimensions(plan.width, plan.height, max_pixels)?;
+    let (slot_width, slot_height) = secret_slots(plan.width, plan.height, interleave);
+
+    let resized_source_image = fit_source(source_image, plan, options);
+    let resized_secret_image = match plan.fit {
+        ImageFit::ResizeSource | ImageFit::ExpandSource => secret_image.clone(),
+        ImageFit::ResizeSecret => {
+            debug!(
+                "Resizing secret from {}x{} to {}x{}",
+                secret_width, secret_height, slot_width, slot_height
+            );
+            resize_with_options(secret_image, slot_width, slot_height, options)
+        }
+        ImageFit::ExpandSecret => {
+            debug!(
+                "Expanding secret from {}x{} to {}x{}",
+                secret_width, secret_height, slot_width, slot_height
+            );
+            expand_with_mode(secret_image, slot_width, slot_height, expand_mode)
+        }
+        ImageFit::FitSecret => fit_secret(secret_image, slot_width, slot_height),
+    };
+
+    let source_buffer = resized_source_image.to_rgb8();
+    let secret_buffer = resized_secret_image.to_rgb8();
+
+    debug!(
+        "Hiding the secret in {} bits per channel starting at bit {}{}",
+        bits,
+        offset,
+        if interleave { ", interleaved" } else { "" }
+    );
+
+    let mut hidden_buffer = ImageBuffer::new(source_buffer.width(), source_buffer.height());
+    let masks = bits.0.map(|bits| ((1u8 << bits) - 1) << offset);
+    progress::expect(source_buffer.width() as u64 * source_buffer.height() as u64);
+
+    for (x, y, source_pixel) in source_buffer.enumerate_pixels() {
+        let mut hidden_pixel = Rgb([0u8; 3]);
+
+        // With an interleaved secret, the left pixel of each pair holds the
+        // high half of the secret bits and the right pixel the low half. The
+        // last column of an odd width holds nothing.
+        let (secret_x, low_half) = if interleave {
+            (x / 2, x % 2 == 1)
+        } else {
+            (x, false)
+        };
+        let secret_pixel = secret_buffer
+            .get_pixel_checked(secret_x, y)
+            .copied()
+            .unwrap_or(Rgb([0u8; 3]));
+
+        for i in 0..3 {
+            let bits = bits.channel(i);
+            let source_value = source_pixel[i];
+            let secret_value = if !interleave {
+                secret_pixel[i] >> (8 - bits)
+            } else if low_half {
+                (secret_pixel[i] >> (8 - 2 * bits)) & ((1 << bits) - 1)
+            } else {
+                secret_pixel[i] >> (8 - bits)
+            };
+            let hidden_value = (source_value & !masks[i]) | (secret_value << offset);
+
+            hidden_pixel[i] = hidden_value;
+        }
+
+        hidden_buffer.put_pixel(x, y, hidden_pixel);
+        progress::advance_row(x, source_buffer.width());
+    }
+
+    let image_header = ImageHeader {
+        bits,
+        interleaved: interleave,
+        shape: secret_shape(
+            source_image.dimensions(),
+            secret_image.dimensions(),
+            options,
+        ),
+    };
+
+    // A header cut short would never be recognized, so images smaller than it
+    // are left without one.
+    if header && hidden_buffer.len() < image_header.samples() {
+        warn!(
+            "The image is too small to hold the header, so the number of bits must be given \
+             to decrypt it"
+        );
+    } else if header {
+        write_image_header(&mut hidden_buffer, image_header, offset);
+    }
+
+    Ok(DynamicImage::ImageRgb8(hidden_buffer))
+}
+
+pub const MAX_IMAGE_BITS: u8 = 4;
+
+// Tries every bit depth from the largest down and returns the first one, with
+// its PSNR, which keeps the carrier above `target_psnr` decibels.
+pub fn select_image_bits(
+    source_image: &DynamicImage,
+    secret_image: &DynamicImage,
+    options: &HideImageOptions,
+    target_psnr: f64,
+) -> Result<Option<(u8, f64)>, SecretError> {
+    let plan = plan_image_fit(
+        source_image.dimensions(),
+        secret_image.dimensions(),
+        options,
+    );
+    check_dimensions(plan.width, plan.height, options.max_pixels)?;
+    let fitted_source_image = fit_source(source_image, plan, options);
+
+    for bits in (1..=MAX_IMAGE_BITS).rev() {
+        let options = HideImageOptions {
+            bits: ImageBits::uniform(bits),
+            ..*options
+        };
+        let hidden_image = hide_image(source_image, secret_image, &options)?;
+        let hidden_psnr = psnr(&fitted_source_image, &hidden_image)?;
+
+        debug!("{} bits give a PSNR of {:.2} dB", bits, hidden_psnr);
+
+        if hidden_psnr >= target_psnr {
+            return Ok(Some((bits, hidden_psnr)));
+        }
+    }
+
+    Ok(None)
+}
+
+// Without resizing or expanding, a secret of a different size is cropped or
+// padded with black to the source size, so every hidden pixel is well defined.
+fn fit_secret(secret_image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let (secret_width, secret_height) = secret_image.dimensions();
+
+    if (secret_width, secret_height) == (width, height) {
+        return secret_image.clone();
+    }
+
+    warn!(
+        "The secret is {}x{} but the source is {}x{}, so the secret will be cropped or padded; \
+         resize or expand it to keep the whole secret",
+        secret_width, secret_height, width, height
+    );
+
+    expand_image(secret_image, width, height)
+}
+
+// The size of the secret that fits in a carrier, in pixels.
+fn secret_slots(width: u32, height: u32, interleave: bool) -> (u32, u32) {
+    if interleave {
+        (width / 2, height)
+    } else {
+        (width, height)
+    }
+}
+
+// Scales a `bits`-bit value up to the full 0..=255 range. A channel with no
+// bits has no levels to scale, so it expands to 0.
+pub fn expand_bits(value: u8, bits: u8) -> u8 {
+    if bits == 0 {
+        return 0;
+    }
+    let max_value = (1u32 << bits) - 1;
+    (value as u32 * 255 / max_value) as u8
+}
+
+pub fn decrypt_image(
+    hidden_image: &DynamicImage,
+    bits: ImageBits,
+    offset: u8,
+) -> Result<DynamicImage, SecretError> {
+    bits.check(offset)?;
+    let hidden_buffer = hidden_image.to_rgb8();
+    let mut decrypted_buffer = ImageBuffer::new(hidden_buffer.width(), hidden_buffer.height());
+    let masks = bits.0.map(|bits| (1u8 << bits) - 1);
+    progress::expect(hidden_buffer.width() as u64 * hidden_buffer.height() as u64);
+
+    for (x, y, hidden_pixel) in hidden_buffer.enumerate_pixels() {
+        let mut decrypted_pixel = Rgb([0u8; 3]);
+
+        for i in 0..3 {
+            let hidden_value = hidden_pixel[i];
+
+            let secret_value = (hidden_value >> offset) & masks[i];
+
+            decrypted_pixel[i] = expand_bits(secret_value, bits.channel(i));
+        }
+
+        decrypted_buffer.put_pixel(x, y, decrypted_pixel);
+        progress::advance_row(x, hidden_buffer.width());
+    }
+
+    Ok(DynamicImage::ImageRgb8(decrypted_buffer))
+}
+
+// Rebuilds each secret pixel from the pair of carrier pixels holding it, so the
+// secret comes out with `2 * bits` bits per channel and half the width.
+pub fn decrypt_interleaved_image(
+    hidden_image: &DynamicImage,
+    bits: ImageBits,
+    offset: u8,
+) -> Result<DynamicImage, SecretError> {
+    bits.check(offset)?;
+    let hidden_buffer = hidden_image.to_rgb8();
+    let (width, height) = secret_slots(hidden_buffer.width(), hidden_buffer.height(), true);
+    let mut decrypted_buffer = ImageBuffer::new(width, height);
+    progress::expect(width as u64 * height as u64);
+
+    for (x, y, decrypted_pixel) in decrypted_buffer.enumerate_pixels_mut() {
+        let high_pixel = hidden_buffer.get_pixel(2 * x, y);
+        let low_pixel = hidden_buffer.get_pixel(2 * x + 1, y);
+
+        *decrypted_pixel = Rgb(std::array::from_fn(|i| {
+            let bits = bits.channel(i);
+            let mask = (1u8 << bits) - 1;
+            let high = (high_pixel[i] >> offset) & mask;
+            let low = (low_pixel[i] >> offset) & mask;
+
+            expand_bits(high << bits | low, 2 * bits)
+        }));
+        progress::advance_row(x, width);
+    }
+
+    Ok(DynamicImage::ImageRgb8(decrypted_buffer))
+}
+
+pub fn reconstruct(
+    hidden_image: &DynamicImage,
+    bits: ImageBits,
+    interleaved: bool,
+    dither: bool,
+) -> Result<DynamicImage, SecretError> {
+    let (decrypted_image, secret_bits) = if interleaved {
+        (
+            decrypt_interleaved_image(hidden_image, bits, 0)?,
+            bits.doubled(),
+        )
+    } else {
+        (decrypt_image(hidden_image, bits, 0)?, bits)
+    };
+
+    if dither {
+        Ok(dither_image(&decrypted_image, secret_bits))
+    } else {
+        Ok(decrypted_image)
+    }
+}
+
+// Brings a decrypted secret back to the size it had before hiding. A secret
+// that was cropped to fit comes back padded with black where it was cut off,
+// and a resized one is resized back, which cannot restore the detail lost.
+pub fn restore_secret_shape(
+    decrypted_image: &DynamicImage,
+    shape: SecretShape,
+    filter: FilterType,
+) -> DynamicImage {
+    match shape {
+        SecretShape::Cropped { width, height } => expand_image(decrypted_image, width, height),
+        SecretShape::Resized {
+            width,
+            height,
+            inner_width,
+            inner_height,
+        } => decrypted_image
+            .crop_imm(0, 0, inner_width, inner_height)
+            .resize_exact(width, height, filter),
+    }
+}
+
+// Only the stored levels are known, so the posterized secret is blurred into
+// a smooth estimate which is then diffused back onto those levels with
+// Floyd-Steinberg, trading hard band edges for a mix of neighbouring levels.
+fn dither_image(decrypted_image: &DynamicImage, bits: ImageBits) -> DynamicImage {
+    let blurred_buffer = imageops::blur(&decrypted_image.to_rgb8(), 1.0);
+    let (width, height) = blurred_buffer.dimensions();
+
+    let max_levels = bits.0.map(|bits| (1u32 << bits) - 1);
+
+    let mut values: Vec<f32> = blurred_buffer.as_raw().iter().map(|&v| v as f32).collect();
+    let mut dithered_buffer = ImageBuffer::new(width, height);
+    progress::expect(width as u64 * height as u64);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut dithered_pixel = Rgb([0u8; 3]);
+
+            for i in 0..3 {
+                let index = (y * width + x) as usize * 3 + i;
+                let max_level = max_levels[i] as f32;
+                let step = 255.0 / max_level;
+                let level = (values[index] / step).round().clamp(0.0, max_level) as u8;
+                let dithered_value = expand_bits(level, bits.channel(i));
+                let error = values[index] - dithered_value as f32;
+
+                let mut spread = |dx: i64, dy: u32, weight: f32| {
+                    let nx = x as i64 + dx;
+                    if nx >= 0 && (nx as u32) < width && y + dy < height {
+                        values[((y + dy) * width + nx as u32) as usize * 3 + i] += error * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+
+                dithered_pixel[i] = dithered_value;
+            }
+
+            dithered_buffer.put_pixel(x, y, dithered_pixel);
+        }
+
+        progress::advance(width as u64);
+    }
+
+    DynamicImage::ImageRgb8(dithered_buffer)
+}
+
+// The secret image sits right above the bit plane used for text, so both can
+// share a carrier at the cost of one more low bit of distortion per channel.
+pub fn hide_image_and_text(
+    source_image: &DynamicImage,
+    secret_image: &DynamicImage,
+    text: &str,
+    options: &HideImageOptions,
+) -> Result<DynamicImage, SecretError> {
+    let options = HideImageOptions {
+        offset: TEXT_BITS_8,
+        ..*options
+    };
+
+    let hidden_image = hide_image(source_image, secret_image, &options)?;
+    let text_options = EmbedOptions {
+        header: true,
+        ..EmbedOptions::default()
+    };
+
+    hide_bytes_in_image(&hidden_image, text.as_bytes(), &text_options)
+}
+
+pub fn extract_image_and_text(
+    hidden_image: &DynamicImage,
+    bits: ImageBits,
+) -> Result<(DynamicImage, String), SecretError> {
+    let decrypted_image = decrypt_image(hidden_image, bits, TEXT_BITS_8)?;
+    let text = extract_text_from_image(hidden_image)?;
+
+    Ok((decrypted_image, text))
+}
+
+trait LowBits: Copy {
+    fn low_bits(self, bits: u8) -> u8;
+
+    fn with_low_bits(self, bits: u8, value: u8) -> Self;
+
+    fn matched_low_bits(self, bits: u8, value: u8, up: bool) -> Self;
+}
+
+// LSB matching moves the sample to the nearest value holding the wanted low
+// bits, in either direction, instead of overwriting them. With one bit this
+// adds or subtracts one, `up` choosing when both are equally close.
+fn match_value(current: u32, target: u32, step: u32, max: u32, up: bool) -> u32 {
+    let above = Some(target + step).filter(|&value| value <= max);
+    let below = target.checked_sub(step);
+
+    [Some(target), above, below]
+        .into_iter()
+        .flatten()
+        .min_by_key(|&value| (value.abs_diff(current), (value > current) != up))
+        .unwrap()
+}
+
+// A small xorshift generator for the LSB matching directions and the noise of
+// stripped images. Its seed is fixed so that the same inputs still produce the
+// same output.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new() -> Self {
+        Self(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn step(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.step() & 1 == 1
+    }
+
+    fn next_u8(&mut self) -> u8 {
+        (self.step() >> 56) as u8
+    }
+}
+
+impl LowBits for u8 {
+    fn low_bits(self, bits: u8) -> u8 {
+        self & ((1u16 << bits) - 1) as u8
+    }
+
+    fn with_low_bits(self, bits: u8, value: u8) -> Self {
+        let mask = ((1u16 << bits) - 1) as u8;
+        (self & !mask) | (value & mask)
+    }
+
+    fn matched_low_bits(self, bits: u8, value: u8, up: bool) -> Self {
+        let target = self.with_low_bits(bits, value) as u32;
+        match_value(self as u32, target, 1 << bits, u8::MAX as u32, up) as u8
+    }
+}
+
+impl LowBits for u16 {
+    fn low_bits(self, bits: u8) -> u8 {
+        (self & ((1u32 << bits) - 1) as u16) as u8
+    }
+
+    fn with_low_bits(self, bits: u8, value: u8) -> Self {
+        let mask = ((1u32 << bits) - 1) as u16;
+        (self & !mask) | (value as u16 & mask)
+    }
+
+    fn matched_low_bits(self, bits: u8, value: u8, up: bool) -> Self {
+        let target = self.with_low_bits(bits, value) as u32;
+        match_value(self as u32, target, 1 << bits, u16::MAX as u32, up) as u16
+    }
+}
+
+const TEXT_BITS_8: u8 = 1;
+const TEXT_BITS_16: u8 = 2;
+const MAX_TEXT_BITS: u8 = 8;
+
+fn is_16_bit(color: ColorType) -> bool {
+    matches!(
+        color,
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+    )
+}
+
+fn is_grayscale(color: ColorType) -> bool {
+    matches!(color, ColorType::L8 | ColorType::L16)
+}
+
+fn embed_bytes<'a, S: LowBits + 'a>(
+    samples: impl Iterator<Item = &'a mut S>,
+    bits_per_sample: u8,
+    matching: bool,
+    bytes: impl Iterator<Item = u8>,
+) {
+    let mut directions = Xorshift::new();
+    progress::expect(bytes.size_hint().0 as u64);
+
+    let bytes = bytes.inspect(|_| progress::advance(1));
+    let mut bits = bytes.flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+
+    for sample in samples {
+        let mut value = 0u8;
+        let mut count = 0;
+
+        while count < bits_per_sample {
+            match bits.next() {
+                Some(bit) => value = (value << 1) | bit,
+                None => break,
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            break;
+        }
+
+        let kept_bits = bits_per_sample - count;
+        let kept_value = sample.low_bits(kept_bits);
+        let value = (value << kept_bits) | kept_value;
+
+        *sample = if matching {
+            sample.matched_low_bits(bits_per_sample, value, directions.next_bool())
+        } else {
+            sample.with_low_bits(bits_per_sample, value)
+        };
+    }
+}
+
+fn extract_bytes<S: LowBits>(
+    samples: impl Iterator<Item = S>,
+    bits_per_sample: u8,
+) -> impl Iterator<Item = u8> {
+    let mut bits = samples.flat_map(move |sample| {
+        let value = sample.low_bits(bits_per_sample);
+        (0..bits_per_sample)
+            .rev()
+            .map(move |bit| (value >> bit) & 1)
+    });
+
+    std::iter::from_fn(move || {
+        let mut byte = 0u8;
+
+        for _ in 0..8 {
+            byte = (byte << 1) | bits.next()?;
+        }
+
+        Some(byte)
+    })
+}
+
+fn text_bits_per_sample(color: ColorType) -> u8 {
+    if is_16_bit(color) {
+        TEXT_BITS_16
+    } else {
+        TEXT_BITS_8
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmbedOptions {
+    pub region: Option<Region>,
+    pub channels: Channels,
+    pub bits: Option<u8>,
+    pub lsb_matching: bool,
+    pub fill_fraction: Option<f64>,
+    // Writes a mode header before the payload. Extraction finds the header on
+    // its own, whatever the options.
+    pub header: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Placement {
+    region: Region,
+    channels: Channels,
+    channel_count: usize,
+    bits: u8,
+    matching: bool,
+    // Samples at the start of the region and channels that hold the mode
+    // header, which the payload starts after.
+    skip: usize,
+}
+
+impl Placement {
+    // Grayscale images keep their single channel, and the others are read as
+    // RGB, or as RGBA when the alpha channel carries data.
+    fn new(color: ColorType, region: Region, channels: Channels) -> Self {
+        let channel_count = if is_grayscale(color) {
+            1
+        } else if channels.has_alpha() {
+            4
+        } else {
+            3
+        };
+
+        Self {
+            region,
+            channels,
+            channel_count,
+            bits: text_bits_per_sample(color),
+            matching: false,
+            skip: 0,
+        }
+    }
+
+    fn full(width: u32, height: u32, color: ColorType, channels: Channels) -> Self {
+        let region = Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+
+        Self::new(color, region, channels)
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        if self.channel_count == 1 {
+            1
+        } else {
+            self.channels.count()
+        }
+    }
+
+    fn contains(&self, width: u32, index: usize) -> bool {
+        let pixel = (index / self.channel_count) as u32;
+        let channel = index % self.channel_count;
+
+        self.region.contains(pixel % width, pixel / width)
+            && (self.channel_count == 1 || self.channels.contains(channel))
+    }
+}
+
+impl EmbedOptions {
+    // The share of the stream capacity that embedding may use, so that part of
+    // the carrier's low bits are always left as they were.
+    fn fill_limit(&self, capacity: usize) -> Result<usize, SecretError> {
+        match self.fill_fraction {
+            Some(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+                Err(SecretError::InvalidFillFraction(fraction))
+            }
+            Some(fraction) => Ok((capacity as f64 * fraction) as usize),
+            None => Ok(capacity),
+        }
+    }
+
+    fn placement(&self, image: &DynamicImage) -> Result<Placement, SecretError> {
+        let (width, height) = image.dimensions();
+        self.placement_for(width, height, image.color())
+    }
+
+    fn placement_for(
+        &self,
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> Result<Placement, SecretError> {
+        if self.channels.has_alpha() && !color.has_alpha() {
+            return Err(SecretError::NoAlphaChannel);
+        }
+
+        let mut placement = match self.region {
+            Some(region) if region.fits(width, height) => {
+                Placement::new(color, region, self.channels)
+            }
+            Some(region) => {
+                return Err(SecretError::RegionOutOfBounds {
+                    region,
+                    width,
+                    height,
+                })
+            }
+            None => Placement::full(width, height, color, self.channels),
+        };
+        placement.matching = self.lsb_matching;
+
+        if self.header {
+            placement.skip = mode_header_samples(self.region.is_some(), false);
+        }
+
+        match self.bits {
+            Some(bits) if bits == 0 || bits > MAX_TEXT_BITS => Err(SecretError::InvalidBits(bits)),
+            Some(bits) => {
+                placement.bits = bits;
+                Ok(placement)
+            }
+            None => Ok(placement),
+        }
+    }
+}
+
+// Capacities are computed in u64 so that huge images cannot overflow them on
+// 32-bit platforms, and are then clamped to what a slice could ever hold.
+fn available_bits(placement: &Placement) -> u64 {
+    placement
+        .region
+        .pixel_count()
+        .saturating_mul(placement.samples_per_pixel() as u64)
+        .saturating_sub(placement.skip as u64)
+        .saturating_mul(placement.bits as u64)
+}
+
+fn stream_capacity(placement: &Placement) -> usize {
+    usize::try_from(available_bits(placement) / 8).unwrap_or(usize::MAX)
+}
+
+fn length_header(payload: &[u8]) -> Result<u32, SecretError> {
+    u32::try_from(payload.len()).map_err(|_| SecretError::PayloadTooLarge(payload.len()))
+}
+
+// The payload length is stored before it as an LEB128 varint: seven bits per
+// byte, least significant first, with the top bit set on every byte but the
+// last. A 64-bit length takes at most ten bytes.
+const MAX_LENGTH_LEN: usize = 10;
+
+fn length_len(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()).div_ceil(7).max(1) as usize
+}
+
+fn encode_length(mut len: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(length_len(len));
+
+    while len >= 0x80 {
+        bytes.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    bytes.push(len as u8);
+
+    bytes
+}
+
+// The largest payload that fits in the stream along with its length.
+fn payload_room(stream_capacity: usize) -> usize {
+    let len = stream_capacity.saturating_sub(length_len(stream_capacity));
+
+    if len + 1 + length_len(len + 1) <= stream_capacity {
+        len + 1
+    } else {
+        len
+    }
+}
+
+pub fn payload_capacity(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<usize, SecretError> {
+    let placement = options.placement(image)?;
+
+    Ok(payload_room(
+        options.fill_limit(stream_capacity(&placement))?,
+    ))
+}
+
+fn embed_samples<S: LowBits>(
+    samples: &mut [S],
+    width: u32,
+    placement: &Placement,
+    bytes: impl Iterator<Item = u8>,
+) {
+    let samples = samples
+        .iter_mut()
+        .enumerate()
+        .filter(|(index, _)| placement.contains(width, *index))
+        .map(|(_, sample)| sample)
+        .skip(placement.skip);
+
+    embed_bytes(samples, placement.bits, placement.matching, bytes);
+}
+
+fn embed_stream(
+    image: &DynamicImage,
+    placement: &Placement,
+    bytes: impl Iterator<Item = u8>,
+) -> DynamicImage {
+    let width = image.width();
+
+    match (placement.channel_count, is_16_bit(image.color())) {
+        (1, true) => {
+            let mut hidden_image = image.to_luma16();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageLuma16(hidden_image)
+        }
+        (1, false) => {
+            let mut hidden_image = image.to_luma8();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageLuma8(hidden_image)
+        }
+        (4, true) => {
+            let mut hidden_image = image.to_rgba16();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageRgba16(hidden_image)
+        }
+        (4, false) => {
+            let mut hidden_image = image.to_rgba8();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageRgba8(hidden_image)
+        }
+        (_, true) => {
+            let mut hidden_image = image.to_rgb16();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageRgb16(hidden_image)
+        }
+        (_, false) => {
+            let mut hidden_image = image.to_rgb8();
+            embed_samples(&mut hidden_image, width, placement, bytes);
+
+            DynamicImage::ImageRgb8(hidden_image)
+        }
+    }
+}
+
+fn extract_samples<S: LowBits + 'static>(
+    samples: Vec<S>,
+    width: u32,
+    placement: Placement,
+) -> Box<dyn Iterator<Item = u8>> {
+    let samples = samples
+        .into_iter()
+        .enumerate()
+        .filter(move |(index, _)| placement.contains(width, *index))
+        .map(|(_, sample)| sample)
+        .skip(placement.skip);
+
+    Box::new(extract_bytes(samples, placement.bits))
+}
+
+fn extract_stream(image: &DynamicImage, placement: Placement) -> Box<dyn Iterator<Item = u8>> {
+    let width = image.width();
+
+    match (placement.channel_count, is_16_bit(image.color())) {
+        (1, true) => {
+            let samples = image.to_luma16().into_raw();
+            extract_samples(samples, width, placement)
+        }
+        (1, false) => {
+            let samples = image.to_luma8().into_raw();
+            extract_samples(samples, width, placement)
+        }
+        (4, true) => {
+            let samples = image.to_rgba16().into_raw();
+            extract_samples(samples, width, placement)
+        }
+        (4, false) => {
+            let samples = image.to_rgba8().into_raw();
+            extract_samples(samples, width, placement)
+        }
+        (_, true) => {
+            let samples = image.to_rgb16().into_raw();
+            extract_samples(samples, width, placement)
+        }
+        (_, false) => {
+            let samples = image.to_rgb8().into_raw();
+            extract_samples(samples, width, placement)
+        }
+    }
+}
+
+// What embedding a payload took: its length, the pixels the stream reaches
+// into, and the payload capacity of the carrier, regardless of any fill
+// fraction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmbedReport {
+    pub bytes_written: usize,
+    pub pixels_used: u64,
+    pub capacity: usize,
+}
+
+impl EmbedReport {
+    fn new(placement: &Placement, payload_len: usize, stream_len: usize, capacity: usize) -> Self {
+        let samples =
+            (stream_len as u64 * 8).div_ceil(placement.bits as u64) + placement.skip as u64;
+        let pixels_used = samples
+            .div_ceil(placement.samples_per_pixel() as u64)
+            .min(placement.region.pixel_count());
+
+        Self {
+            bytes_written: payload_len,
+            pixels_used,
+            capacity,
+        }
+    }
+
+    // The share of the capacity taken by the payload, in percent.
+    pub fn capacity_used(&self) -> f64 {
+        if self.capacity == 0 {
+            return 100.0;
+        }
+
+        self.bytes_written as f64 * 100.0 / self.capacity as f64
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtractReport {
+    pub bytes_read: usize,
+}
+
+pub fn hide_bytes_in_image(
+    image: &DynamicImage,
+    payload: &[u8],
+    options: &EmbedOptions,
+) -> Result<DynamicImage, SecretError> {
+    hide_bytes_in_image_with_report(image, payload, options).map(|(hidden_image, _)| hidden_image)
+}
+
+pub fn hide_bytes_in_image_with_report(
+    image: &DynamicImage,
+    payload: &[u8],
+    options: &EmbedOptions,
+) -> Result<(DynamicImage, EmbedReport), SecretError> {
+    hide_payload_in_image(image, payload, options, PayloadKind::Bytes)
+}
+
+// The kind is only recorded in the mode header, so it is lost without one.
+pub(crate) fn hide_payload_in_image(
+    image: &DynamicImage,
+    payload: &[u8],
+    options: &EmbedOptions,
+    kind: PayloadKind,
+) -> Result<(DynamicImage, EmbedReport), SecretError> {
+    let placement = options.placement(image)?;
+    let capacity = payload_room(stream_capacity(&placement));
+    let allowed = payload_room(options.fill_limit(stream_capacity(&placement))?);
+    let length = encode_length(payload.len());
+
+    debug!(
+        "Embedding {} bytes into a capacity of {} bytes, {} of which may be used",
+        payload.len(),
+        capacity,
+        allowed
+    );
+
+    // Carriers too small for the length header would otherwise accept an
+    // empty payload and keep only part of the header.
+    if stream_capacity(&placement) < length.len() {
+        return Err(SecretError::InsufficientCapacity {
+            needed: payload.len() + length.len(),
+            available: stream_capacity(&placement),
+        });
+    }
+
+    if payload.len() > capacity {
+        return Err(SecretError::InsufficientCapacity {
+            needed: payload.len(),
+            available: capacity,
+        });
+    }
+
+    if payload.len() > allowed {
+        return Err(SecretError::FillFractionExceeded {
+            needed: payload.len(),
+            allowed,
+            capacity,
+        });
+    }
+
+    let stream_len = length.len() + payload.len();
+    let bytes = length.into_iter().chain(payload.iter().copied());
+
+    let hidden_image = embed_stream(image, &placement, bytes);
+    let report = EmbedReport::new(&placement, payload.len(), stream_len, capacity);
+
+    if !options.header {
+        return Ok((hidden_image, report));
+    }
+
+    let header = ModeHeader {
+        bits: placement.bits,
+        channels: options.channels,
+        region: options.region,
+        parity: 0,
+        compressed: false,
+        encrypted: false,
+        kind,
+        payload_len: length_header(payload)?,
+    };
+
+    Ok((write_mode_header(hidden_image, &header)?, report))
+}
+
+pub fn hide_text_in_image(image: &DynamicImage, text: &str) -> Result<DynamicImage, SecretError> {
+    hide_bytes_in_image(image, text.as_bytes(), &EmbedOptions::default())
+}
+
+pub fn hide_text_in_image_with_report(
+    image: &DynamicImage,
+    text: &str,
+) -> Result<(DynamicImage, EmbedReport), SecretError> {
+    hide_bytes_in_image_with_report(image, text.as_bytes(), &EmbedOptions::default())
+}
+
+pub fn hide_in_images<'a, K, I, E, F>(
+    images: I,
+    hide: F,
+) -> impl Iterator<Item = (K, Result<DynamicImage, SecretError>)> + 'a
+where
+    I: IntoIterator<Item = (K, Result<DynamicImage, E>)>,
+    I::IntoIter: 'a,
+    E: Into<SecretError>,
+    F: Fn(&DynamicImage) -> Result<DynamicImage, SecretError> + 'a,
+{
+    images.into_iter().map(move |(key, image)| {
+        let hidden_image = image.map_err(Into::into).and_then(|image| hide(&image));
+
+        (key, hidden_image)
+    })
+}
+
+pub fn hide_text_in_images<'a, K, I>(
+    images: I,
+    text: &'a str,
+) -> impl Iterator<Item = (K, Result<DynamicImage, SecretError>)> + 'a
+where
+    I: IntoIterator<Item = (K, ImageResult<DynamicImage>)>,
+    I::IntoIter: 'a,
+{
+    hide_in_images(images, move |image| hide_text_in_image(image, text))
+}
+
+// Images with a mode header are extracted with the options it holds instead
+// of `options`.
+pub fn extract_bytes_from_image(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<Vec<u8>, SecretError> {
+    match find_mode_header(image, options.region) {
+        Some(header) => extract_with_mode(image, &header),
+        None => extract_lsb_bytes(image, options),
+    }
+}
+
+pub(crate) fn extract_lsb_bytes(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<Vec<u8>, SecretError> {
+    let placement = options.placement(image)?;
+    let capacity = stream_capacity(&placement);
+
+    extract_payload(extract_stream(image, placement), capacity)
+}
+
+pub fn extract_text_from_image(image: &DynamicImage) -> Result<String, SecretError> {
+    extract_text_from_image_with_report(image).map(|(text, _)| text)
+}
+
+pub fn extract_text_from_image_with_report(
+    image: &DynamicImage,
+) -> Result<(String, ExtractReport), SecretError> {
+    let payload = extract_bytes_from_image(image, &EmbedOptions::default())?;
+    let report = ExtractReport {
+        bytes_read: payload.len(),
+    };
+
+    Ok((String::from_utf8(payload)?, report))
+}
+
+// The length is checked against what the image can hold before reading on,
+// since an image without a payload declares an arbitrary one. A length that
+// runs past the stream or past ten bytes is read as the largest one, which
+// never fits.
+fn read_length(
+    bytes: &mut impl Iterator<Item = u8>,
+    capacity: usize,
+) -> Result<usize, SecretError> {
+    if capacity == 0 {
+        return Err(SecretError::InsufficientCapacity {
+            needed: 1,
+            available: capacity,
+        });
+    }
+
+    let mut len = 0usize;
+    for (index, byte) in bytes.take(MAX_LENGTH_LEN.min(capacity)).enumerate() {
+        let shift = 7 * index as u32;
+        let value = (byte & 0x7F) as usize;
+
+        if shift >= usize::BITS || (value << shift) >> shift != value {
+            break;
+        }
+        len |= value << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(len);
+        }
+    }
+
+    Ok(usize::MAX)
+}
+
+fn extract_payload(
+    mut bytes: impl Iterator<Item = u8>,
+    capacity: usize,
+) -> Result<Vec<u8>, SecretError> {
+    let payload_len = read_length(&mut bytes, capacity)?;
+    debug!("Extracting {} bytes", payload_len);
+
+    if payload_len > payload_room(capacity) {
+        return Err(SecretError::DeclaredLengthExceedsCapacity {
+            declared: payload_len,
+            capacity: payload_room(capacity),
+        });
+    }
+
+    progress::expect(payload_len as u64);
+    Ok(bytes
+        .take(payload_len)
+        .inspect(|_| progress::advance(1))
+        .collect())
+}
+
+// Stretches each channel on its own to the full 0-255 range, so the color
+// balance is kept. Channels holding a single value are left as they are.
+pub fn normalize_image(image: &DynamicImage) -> DynamicImage {
+    let buffer = image.to_rgb8();
+    let mut normalized_buffer = ImageBuffer::new(buffer.width(), buffer.height());
+
+    let mut min_values = [255u8; 3];
+    let mut max_values = [0u8; 3];
+
+    for pixel in buffer.pixels() {
+        for i in 0..3 {
+            min_values[i] = min_values[i].min(pixel[i]);
+            max_values[i] = max_values[i].max(pixel[i]);
+        }
+    }
+
+    progress::expect(buffer.width() as u64 * buffer.height() as u64);
+
+    for (x, y, pixel) in buffer.enumerate_pixels() {
+        let mut normalized_pixel = Rgb([0u8; 3]);
+
+        for i in 0..3 {
+            let (value, min_value, max_value) = (pixel[i], min_values[i], max_values[i]);
+
+            normalized_pixel[i] = if max_value > min_value {
+                let scaled = (value - min_value) as f32 / (max_value - min_value) as f32 * 255.0;
+                scaled.round().clamp(0.0, 255.0) as u8
+            } else {
+                value
+            };
+        }
+
+        normalized_buffer.put_pixel(x, y, normalized_pixel);
+        progress::advance_row(x, buffer.width());
+    }
+
+    DynamicImage::ImageRgb8(normalized_buffer)
+}
+
+pub fn resize_image(
+    image: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    keep_aspect: bool,
+    filter: FilterType,
+) -> DynamicImage {
+    if keep_aspect {
+        let (width, height) =
+            fit_dimensions(image.width(), image.height(), target_width, target_height);
+        let resized_image = image.resize_exact(width, height, filter);
+        expand_image(&resized_image, target_width, target_height)
+    } else {
+        image.resize_exact(target_width, target_height, filter)
+    }
+}
+
+// The largest size with the aspect ratio of `width` by `height` that fits in
+// the target, rounded the same way as `DynamicImage::resize`. The header
+// records it so that the padding can be cropped off again.
+pub(crate) fn fit_dimensions(
+    width: u32,
+    height: u32,
+    target_width: u32,
+    target_height: u32,
+) -> (u32, u32) {
+    let ratio = f64::min(
+        target_width as f64 / width as f64,
+        target_height as f64 / height as f64,
+    );
+    let scale = |value: u32| ((value as f64 * ratio).round() as u32).max(1);
+
+    (scale(width), scale(height))
+}
+
+fn expand_with_mode(
+    image: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    mode: ExpandMode,
+) -> DynamicImage {
+    match mode {
+        ExpandMode::Pad => expand_image(image, target_width, target_height),
+        ExpandMode::Tile => tile_image(image, target_width, target_height),
+    }
+}
+
+pub fn expand_image(
+    source_image: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+) -> DynamicImage {
+    let (source_width, source_height) = source_image.dimensions();
+
+    let source_buffer = source_image.to_rgb8();
+    let mut expanded_buffer = ImageBuffer::new(target_width, target_height);
+
+    for (x, y, pixel) in expanded_buffer.enumerate_pixels_mut() {
+        if x < source_width && y < source_height {
+            let source_pixel = source_buffer.get_pixel(x, y);
+            *pixel = *source_pixel;
+        } else {
+            *pixel = Rgb([0, 0, 0]);
+        }
+    }
+
+    DynamicImage::ImageRgb8(expanded_buffer)
+}
+
+// Repeats the image to fill the target, so that every pixel keeps real image
+// content instead of black padding.
+pub fn tile_image(
+    source_image: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+) -> DynamicImage {
+    let (source_width, source_height) = source_image.dimensions();
+
+    if source_width == 0 || source_height == 0 {
+        return expand_image(source_image, target_width, target_height);
+    }
+
+    let source_buffer = source_image.to_rgb8();
+    let tiled_buffer = ImageBuffer::from_fn(target_width, target_height, |x, y| {
+        *source_buffer.get_pixel(x % source_width, y % source_height)
+    });
+
+    DynamicImage::ImageRgb8(tiled_buffer)
+}
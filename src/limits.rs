@@ -0,0 +1,59 @@
+use std::{io::Cursor, path::Path};
+
+use image::{
+    io::{Limits, Reader},
+    DynamicImage,
+};
+
+use crate::SecretError;
+
+// Large enough for any camera, small enough that an image claiming more is
+// refused before its pixels are allocated.
+pub const DEFAULT_MAX_PIXELS: u64 = 400_000_000;
+
+// Four 32-bit float channels, the widest color type an image decodes to.
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+pub fn check_dimensions(width: u32, height: u32, max_pixels: u64) -> Result<(), SecretError> {
+    if width as u64 * height as u64 > max_pixels {
+        return Err(SecretError::DimensionsTooLarge {
+            width,
+            height,
+            max_pixels,
+        });
+    }
+
+    Ok(())
+}
+
+// The decoders' own allocation limit, 512 MiB by default, would otherwise
+// refuse images well under the pixel limit, so it follows the pixel limit.
+fn reader_limits(max_pixels: u64) -> Limits {
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(max_pixels.saturating_mul(MAX_BYTES_PER_PIXEL));
+    limits
+}
+
+// The dimensions are read from the header first, so that an image claiming to
+// be huge is never decoded.
+pub fn open_image(path: &Path, max_pixels: u64) -> Result<DynamicImage, SecretError> {
+    let (width, height) = Reader::open(path)?
+        .with_guessed_format()?
+        .into_dimensions()?;
+    check_dimensions(width, height, max_pixels)?;
+
+    let mut reader = Reader::open(path)?.with_guessed_format()?;
+    reader.limits(reader_limits(max_pixels));
+    Ok(reader.decode()?)
+}
+
+pub fn load_image(bytes: &[u8], max_pixels: u64) -> Result<DynamicImage, SecretError> {
+    let reader = || Reader::new(Cursor::new(bytes)).with_guessed_format();
+
+    let (width, height) = reader()?.into_dimensions()?;
+    check_dimensions(width, height, max_pixels)?;
+
+    let mut reader = reader()?;
+    reader.limits(reader_limits(max_pixels));
+    Ok(reader.decode()?)
+}
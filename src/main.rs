@@ -1,241 +1,768 @@
-use std::path::Path;
-
-use clap::{arg, Command};
-use image::{imageops::FilterType::Lanczos3, DynamicImage, GenericImageView, ImageBuffer, Rgb};
-
-fn hide_image(
-    source_image: &DynamicImage,
-    secret_image: &DynamicImage,
-    resize: bool,
-    expand: bool,
-) -> DynamicImage {
-    let (source_width, source_height) = source_image.dimensions();
-    let (secret_width, secret_height) = secret_image.dimensions();
-
-    let (resized_source_image, resized_secret_image) =
-        if source_image.dimensions() < secret_image.dimensions() {
-            if resize {
-                (
-                    source_image.resize_exact(secret_width, secret_height, Lanczos3),
-                    secret_image.clone(),
-                )
-            } else if expand {
-                (
-                    expand_image(source_image, secret_width, secret_height),
-                    secret_image.clone(),
-                )
-            } else {
-                (source_image.clone(), secret_image.clone())
-            }
-        } else {
-            if resize {
-                (
-                    source_image.clone(),
-                    secret_image.resize_exact(source_width, source_height, Lanczos3),
-                )
-            } else if expand {
-                (
-                    source_image.clone(),
-                    expand_image(secret_image, source_width, source_height),
-                )
-            } else {
-                (source_image.clone(), secret_image.clone())
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use clap::{arg, parser::ValueSource, Arg, ArgGroup, ArgMatches, Command};
+use image::{
+    codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+    imageops::FilterType,
+    ColorType, DynamicImage, GenericImageView, ImageFormat,
+};
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use log::{error, info, warn, LevelFilter};
+use secret::{
+    append_tagged_payload, check_dimensions, copy_metadata, declared_payload, diff_images,
+    extract_bit_plane, extract_bytes_from_gif, extract_image_and_text, extract_meta_from_image,
+    extract_tagged_payload, hide_bytes_across_images, hide_bytes_in_gif, hide_image,
+    hide_image_and_text, hide_in_images, hide_meta_in_image, is_lossy_webp, join_bytes_from_images,
+    load_image, normalize_image, open_image, plan_image_fit, progress, read_declared_payload,
+    read_image_header, reconstruct, reset_progress, restore_secret_shape, secret_shape,
+    select_image_bits, strip_lsb, Channels, EccStego, EmbedOptions, ExpandMode, HideImageOptions,
+    ImageBits, LsbStego, Metadata, Progress, Region, SecretError, Steganographer,
+    DEFAULT_MAX_PIXELS,
+};
+use serde_json::{json, Value};
+
+const STDOUT_PATH: &str = "-";
+const STDIN_PATH: &str = "-";
+
+// Stdin can only be read once, so its bytes are kept for commands that look
+// at the input more than once.
+static STDIN_BYTES: OnceLock<Vec<u8>> = OnceLock::new();
+
+// URLs are fetched once each for the same reason, and so that every look at
+// an input sees the same response.
+static URL_BYTES: OnceLock<Mutex<HashMap<String, Vec<u8>>>> = OnceLock::new();
+
+// Set from --json, --max-pixels and --quiet before any command runs.
+static JSON_OUTPUT: OnceLock<bool> = OnceLock::new();
+static MAX_PIXELS: OnceLock<u64> = OnceLock::new();
+static SHOW_PROGRESS: OnceLock<bool> = OnceLock::new();
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+fn max_pixels() -> u64 {
+    MAX_PIXELS.get().copied().unwrap_or(DEFAULT_MAX_PIXELS)
+}
+
+fn json_output() -> bool {
+    JSON_OUTPUT.get().copied().unwrap_or(false)
+}
+
+// Runs a long library call with a progress bar on stderr, which a thread
+// updates from the library's counters until the call returns. The bar is only
+// drawn on a terminal, and never with --quiet or --json.
+fn with_progress<T>(operation: impl FnOnce() -> T) -> T {
+    if !SHOW_PROGRESS.get().copied().unwrap_or(false) {
+        return operation();
+    }
+
+    reset_progress();
+    let style = ProgressStyle::with_template("{wide_bar} {human_pos}/{human_len} {rate} {eta}")
+        .unwrap()
+        .with_key("rate", |state: &ProgressState, out: &mut dyn fmt::Write| {
+            write!(out, "{:.0}/s", state.per_sec()).unwrap()
+        });
+    let bar = ProgressBar::no_length().with_style(style);
+
+    // The sender is dropped when the call returns or panics, which stops the
+    // thread.
+    let (sender, receiver) = mpsc::channel::<()>();
+    let result = thread::scope(|scope| {
+        let bar = &bar;
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(PROGRESS_INTERVAL) {
+                // Nothing is drawn before the first loop starts.
+                let Progress { done, total } = progress();
+                if total > 0 {
+                    bar.set_length(total);
+                    bar.set_position(done);
+                }
             }
-        };
+        });
 
-    let source_buffer = resized_source_image.to_rgb8();
-    let secret_buffer = resized_secret_image.to_rgb8();
+        let _sender = sender;
+        operation()
+    });
 
-    let mut hidden_buffer = ImageBuffer::new(source_width, source_height);
+    bar.finish_and_clear();
+    result
+}
 
-    for (x, y, source_pixel) in source_buffer.enumerate_pixels() {
-        let mut hidden_pixel = Rgb([0u8; 3]);
+// With --json, the result of a command is printed as one JSON object on
+// stdout, or on stderr when the output file itself goes to stdout.
+fn print_json(result: Value) {
+    if !json_output() {
+        return;
+    }
 
-        let secret_pixel = secret_buffer.get_pixel(x, y);
+    if result["output"] == STDOUT_PATH {
+        eprintln!("{}", result);
+    } else {
+        println!("{}", result);
+    }
+}
 
-        for i in 0..3 {
-            let source_value = source_pixel[i];
-            let secret_value = secret_pixel[i];
-            let hidden_value = (source_value & 0xFC) | (secret_value >> 6);
+fn fail(context: &str, error: impl Into<SecretError>) -> ! {
+    let error = error.into();
 
-            hidden_pixel[i] = hidden_value;
-        }
+    if json_output() {
+        println!("{}", json!({ "error": error.to_json() }));
+    } else {
+        error!("{}: {}", context, error);
+    }
 
-        hidden_buffer.put_pixel(x, y, hidden_pixel);
+    process::exit(1);
+}
+
+// Fails on errors of the command line, which have no `SecretError`. `fields`
+// is a JSON object that gets the kind and the message.
+fn fail_with(kind: &str, message: String, mut fields: Value) -> ! {
+    if json_output() {
+        fields["kind"] = json!(kind);
+        fields["message"] = json!(message);
+        println!("{}", json!({ "error": fields }));
+    } else {
+        error!("{}", message);
     }
 
-    DynamicImage::ImageRgb8(hidden_buffer)
+    process::exit(1);
 }
 
-fn decrypt_image(hidden_image: &DynamicImage) -> DynamicImage {
-    let hidden_buffer = hidden_image.to_rgb8();
-    let mut decrypted_buffer = ImageBuffer::new(hidden_buffer.width(), hidden_buffer.height());
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
 
-    for (x, y, hidden_pixel) in hidden_buffer.enumerate_pixels() {
-        let mut decrypted_pixel = Rgb([0u8; 3]);
+// Inputs that are not files have no extension, so their format is guessed
+// from their contents.
+fn is_stream_input(path: &str) -> bool {
+    path == STDIN_PATH || is_url(path)
+}
 
-        for i in 0..3 {
-            let hidden_value = hidden_pixel[i];
+#[cfg(feature = "http")]
+fn fetch_url(url: &str) -> io::Result<Vec<u8>> {
+    let response = ureq::get(url).call().map_err(io::Error::other)?;
 
-            let secret_value = hidden_value & 0x03;
+    let mut bytes = Vec::new();
+    response.into_reader().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
 
-            decrypted_pixel[i] = secret_value * 85;
+#[cfg(not(feature = "http"))]
+fn fetch_url(_url: &str) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reading URLs needs the http feature",
+    ))
+}
+
+fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path == STDIN_PATH {
+        if let Some(bytes) = STDIN_BYTES.get() {
+            return Ok(bytes.clone());
         }
 
-        decrypted_buffer.put_pixel(x, y, decrypted_pixel);
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(STDIN_BYTES.get_or_init(|| bytes).clone())
+    } else if is_url(path) {
+        let cache = URL_BYTES.get_or_init(Default::default);
+        if let Some(bytes) = cache.lock().unwrap().get(path) {
+            return Ok(bytes.clone());
+        }
+
+        let bytes = fetch_url(path)?;
+        cache
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), bytes.clone());
+        Ok(bytes)
+    } else {
+        fs::read(path)
     }
+}
 
-    DynamicImage::ImageRgb8(decrypted_buffer)
+fn open_image_source(path: &str) -> Result<DynamicImage, SecretError> {
+    if is_stream_input(path) {
+        load_image(&read_input(path)?, max_pixels())
+    } else {
+        open_image(Path::new(path), max_pixels())
+    }
 }
 
-fn hide_text_in_image(image: &DynamicImage, text: &str) -> DynamicImage {
-    let (width, height) = image.dimensions();
-    let mut hidden_image = image.to_rgb8();
+fn input_format(path: &str) -> Option<ImageFormat> {
+    if is_stream_input(path) {
+        read_input(path)
+            .ok()
+            .and_then(|bytes| image::guess_format(&bytes).ok())
+    } else {
+        ImageFormat::from_path(path).ok()
+    }
+}
 
-    let required_pixels = (text.len() + 4) * 8;
+fn check_output(output: &str, force: bool) {
+    if !force && output != STDOUT_PATH && Path::new(output).exists() {
+        fail_with(
+            "OutputExists",
+            format!("{} already exists, pass --force to overwrite it", output),
+            json!({ "output": output }),
+        );
+    }
+}
 
-    if required_pixels > (width * height).try_into().unwrap() {
-        panic!("Insufficient space in the image to hide the text.");
+// PNGs go through an encoder with fixed settings so the same inputs always
+// produce the same bytes.
+fn write_png(
+    image: &DynamicImage,
+    writer: impl Write,
+    compression: CompressionType,
+) -> image::ImageResult<()> {
+    image.write_with_encoder(PngEncoder::new_with_quality(
+        writer,
+        compression,
+        PngFilterType::Adaptive,
+    ))
+}
+
+fn save_image_file(
+    image: &DynamicImage,
+    path: &Path,
+    compression: CompressionType,
+) -> image::ImageResult<()> {
+    if ImageFormat::from_path(path)? == ImageFormat::Png {
+        write_png(image, BufWriter::new(File::create(path)?), compression)
+    } else {
+        image.save(path)
     }
+}
 
-    let text_len = text.len() as u32;
-    let text_len_bytes = text_len.to_be_bytes();
+fn save_image(
+    image: &DynamicImage,
+    output: &str,
+    compression: CompressionType,
+) -> image::ImageResult<()> {
+    if output == STDOUT_PATH {
+        write_png(image, io::stdout().lock(), compression)
+    } else {
+        save_image_file(image, Path::new(output), compression)
+    }
+}
 
-    let mut x = 0;
-    let mut y = 0;
+// With --keep-metadata, PNG outputs get the color space, text and EXIF
+// metadata of the carrier file so they look like the original.
+fn save_hidden_image(
+    hidden_image: &DynamicImage,
+    output: &str,
+    carrier_path: &str,
+    sub_matches: &ArgMatches,
+) -> Result<(), SecretError> {
+    let compression = png_compression(sub_matches);
+    let is_png = output == STDOUT_PATH
+        || ImageFormat::from_path(output).is_ok_and(|format| format == ImageFormat::Png);
 
-    for byte in &text_len_bytes {
-        for bit in 0..8 {
-            let pixel = hidden_image.get_pixel_mut(x, y);
-            let old_value = pixel[0];
-            let new_value = (old_value & 0xFE) | ((byte >> (7 - bit)) & 1);
-            pixel[0] = new_value;
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-            }
-        }
+    if !sub_matches.get_flag("keep-metadata") {
+        return Ok(save_image(hidden_image, output, compression)?);
     }
 
-    for byte in text.bytes() {
-        for bit in 0..8 {
-            let pixel = hidden_image.get_pixel_mut(x, y);
-            let old_value = pixel[0];
-            let new_value = (old_value & 0xFE) | ((byte >> (7 - bit)) & 1);
-            pixel[0] = new_value;
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-            }
-        }
+    if !is_png {
+        warn!("Metadata is only kept in PNG outputs");
+        return Ok(save_image(hidden_image, output, compression)?);
     }
 
-    DynamicImage::ImageRgb8(hidden_image)
+    let mut png = Vec::new();
+    write_png(hidden_image, &mut png, compression)?;
+
+    let png = copy_metadata(&read_input(carrier_path)?, &png)?;
+    Ok(write_output(&png, output)?)
 }
 
-fn extract_text_from_image(image: &DynamicImage) -> String {
-    let (width, height) = image.dimensions();
-    let hidden_image = image.to_rgb8();
-    let available_pixels = width * height;
+// Describes the file that would be written, for --dry-run.
+fn describe_output(output: &str, width: u32, height: u32, color: ColorType) -> String {
+    let format = if output == STDOUT_PATH {
+        Ok(ImageFormat::Png)
+    } else {
+        ImageFormat::from_path(output)
+    };
 
-    if available_pixels < 32 {
-        panic!("The image is too small to contain the text length and the text itself.");
+    match format {
+        Ok(format) => format!("{} ({}x{} {:?} {:?})", output, width, height, color, format),
+        Err(error) => format!("{} ({}x{}, {})", output, width, height, error),
     }
+}
 
-    let mut extracted_text = String::new();
+fn png_compression(sub_matches: &ArgMatches) -> CompressionType {
+    match sub_matches
+        .get_one::<String>("png-compression")
+        .unwrap()
+        .as_str()
+    {
+        "fast" => CompressionType::Fast,
+        "default" => CompressionType::Default,
+        "best" => CompressionType::Best,
+        _ => unreachable!(),
+    }
+}
 
-    let mut x = 0;
-    let mut y = 0;
+fn write_output(bytes: &[u8], output: &str) -> io::Result<()> {
+    if output == STDOUT_PATH {
+        io::stdout().lock().write_all(bytes)
+    } else {
+        fs::write(output, bytes)
+    }
+}
 
-    let mut text_len_bytes = [0u8; 4];
-    for byte in &mut text_len_bytes {
-        let mut extracted_byte = 0u8;
-        for _ in 0..8 {
-            let pixel = hidden_image.get_pixel(x, y);
-            let lsb = pixel[0] & 1;
-            extracted_byte = (extracted_byte << 1) | lsb;
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-            }
-        }
-        *byte = extracted_byte;
+fn parse_filter(filter: &str) -> FilterType {
+    match filter {
+        "nearest" => FilterType::Nearest,
+        "triangle" => FilterType::Triangle,
+        "catmullrom" => FilterType::CatmullRom,
+        "gaussian" => FilterType::Gaussian,
+        "lanczos3" => FilterType::Lanczos3,
+        _ => unreachable!(),
     }
+}
 
-    let text_len = u32::from_be_bytes(text_len_bytes) as usize;
+fn bits_arg() -> Arg {
+    arg!(--bits <BITS> "Number of low bits per channel carrying the secret, or r,g,b for each")
+        .value_parser(clap::value_parser!(ImageBits))
+        .default_value("2")
+}
 
-    for _ in 0..text_len {
-        let mut extracted_byte = 0u8;
-        for _ in 0..8 {
-            let pixel = hidden_image.get_pixel(x, y);
-            let lsb = pixel[0] & 1;
-            extracted_byte = (extracted_byte << 1) | lsb;
-            x += 1;
-            if x >= width {
-                x = 0;
-                y += 1;
-            }
-        }
-        extracted_text.push(extracted_byte as char);
+fn interleave_arg() -> Arg {
+    arg!(--interleave "Splits each secret pixel over two carrier pixels for twice the bits, at half the width")
+}
+
+fn hide_image_args() -> Vec<Arg> {
+    vec![
+        arg!(--source <SOURCE>),
+        arg!(--secret <SECRET>),
+        arg!(--output <OUTPUT>),
+        arg!(--resize "Resizes the image"),
+        arg!(--expand "Expands the image"),
+        arg!(--"expand-mode" <MODE> "Fills the expanded area with black or copies of the image")
+            .value_parser(["pad", "tile"])
+            .default_value("pad")
+            .requires("expand"),
+        arg!(--"keep-aspect" "Preserves the aspect ratio when resizing").requires("resize"),
+        arg!(--filter <FILTER> "Resize algorithm")
+            .value_parser(["nearest", "triangle", "catmullrom", "gaussian", "lanczos3"])
+            .default_value("lanczos3"),
+        arg!(--"linear-resize" "Resizes in linear light, which keeps the brightness of fine details")
+            .requires("resize"),
+        bits_arg(),
+        arg!(--normalize "Stretches each channel of the source to the full range before hiding"),
+        keep_metadata_arg(),
+    ]
+}
+
+// The source is only normalized on request, since it changes the carrier's
+// visible colors and not just its low bits.
+fn open_source_image(source: &str, sub_matches: &ArgMatches) -> DynamicImage {
+    let source_image = open_image_source(source)
+        .unwrap_or_else(|error| fail("Failed to open source image", error));
+
+    if sub_matches.get_flag("normalize") {
+        with_progress(|| normalize_image(&source_image))
+    } else {
+        source_image
+    }
+}
+
+fn hide_image_options(sub_matches: &ArgMatches) -> HideImageOptions {
+    HideImageOptions {
+        resize: sub_matches.get_flag("resize"),
+        expand: sub_matches.get_flag("expand"),
+        expand_mode: match sub_matches
+            .get_one::<String>("expand-mode")
+            .unwrap()
+            .as_str()
+        {
+            "pad" => ExpandMode::Pad,
+            "tile" => ExpandMode::Tile,
+            _ => unreachable!(),
+        },
+        keep_aspect: sub_matches.get_flag("keep-aspect"),
+        filter: parse_filter(sub_matches.get_one::<String>("filter").unwrap()),
+        linear_resize: sub_matches.get_flag("linear-resize"),
+        bits: *sub_matches.get_one::<ImageBits>("bits").unwrap(),
+        max_pixels: max_pixels(),
+        ..HideImageOptions::default()
     }
+}
+
+fn text_placement_args() -> Vec<Arg> {
+    vec![
+        arg!(--region <REGION> "Only uses the pixels inside x,y,w,h")
+            .value_parser(clap::value_parser!(Region)),
+        arg!(--channels <CHANNELS> "Channels carrying the text, e.g. r, gb, rgb or a")
+            .value_parser(clap::value_parser!(Channels))
+            .default_value("r"),
+        arg!(--"alpha-only" "Only uses the alpha channel, leaving the colors untouched")
+            .conflicts_with("channels"),
+        arg!(--bits <BITS> "Number of low bits per sample carrying the text")
+            .value_parser(clap::value_parser!(u8).range(1..=8)),
+    ]
+}
+
+fn lsb_matching_arg() -> Arg {
+    arg!(--"lsb-matching" "Moves samples up or down by one instead of replacing their low bits")
+}
 
-    extracted_text
+fn fill_fraction_arg() -> Arg {
+    arg!(--"fill-fraction" <FRACTION> "Share of the capacity the text may use, from 0 to 1")
+        .value_parser(parse_fill_fraction)
 }
 
-fn normalize_image(hidden_image: &DynamicImage) -> DynamicImage {
-    let hidden_buffer = hidden_image.to_rgb8();
-    let mut normalized_buffer = ImageBuffer::new(hidden_buffer.width(), hidden_buffer.height());
+fn parse_fill_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        Ok(_) => Err(String::from("expected a fraction above 0 and at most 1")),
+        Err(error) => Err(error.to_string()),
+    }
+}
 
-    let mut min_value = 255u8;
-    let mut max_value = 0u8;
+// Only hiding commands have --lsb-matching and --fill-fraction, extraction
+// reads the same low bits.
+fn lsb_matching(sub_matches: &ArgMatches) -> bool {
+    matches!(
+        sub_matches.try_get_one::<bool>("lsb-matching"),
+        Ok(Some(true))
+    )
+}
+
+// Only the commands that can describe their output without writing it take
+// --dry-run, so that it is refused by the others instead of being ignored.
+fn dry_run_arg() -> Arg {
+    arg!(--"dry-run" "Shows what would be written without writing anything")
+}
 
-    for (_, _, pixel) in hidden_buffer.enumerate_pixels() {
-        for i in 0..3 {
-            let value = pixel[i];
-            min_value = min_value.min(value);
-            max_value = max_value.max(value);
+fn tag_arg() -> Arg {
+    arg!(--tag <TAG> "Keeps the text under this name next to the texts hidden with other tags")
+}
+
+fn keep_metadata_arg() -> Arg {
+    arg!(--"keep-metadata" "Copies the carrier's color profile, text and EXIF into PNG outputs")
+}
+
+fn no_header_arg() -> Arg {
+    arg!(--"no-header" "Leaves out the header that lets decrypt_txt find the options on its own")
+}
+
+// Only hiding commands write the mode header, extraction looks for it in any
+// case and falls back to the options without one.
+fn writes_header(sub_matches: &ArgMatches) -> bool {
+    matches!(
+        sub_matches.try_get_one::<bool>("no-header"),
+        Ok(Some(false))
+    )
+}
+
+fn fill_fraction(sub_matches: &ArgMatches) -> Option<f64> {
+    sub_matches
+        .try_get_one::<f64>("fill-fraction")
+        .ok()
+        .flatten()
+        .copied()
+}
+
+fn channels(sub_matches: &ArgMatches) -> Channels {
+    if sub_matches.get_flag("alpha-only") {
+        Channels::ALPHA
+    } else {
+        *sub_matches.get_one::<Channels>("channels").unwrap()
+    }
+}
+
+fn embed_options(sub_matches: &ArgMatches) -> EmbedOptions {
+    EmbedOptions {
+        region: sub_matches.get_one::<Region>("region").copied(),
+        channels: channels(sub_matches),
+        bits: sub_matches.get_one::<u8>("bits").copied(),
+        lsb_matching: lsb_matching(sub_matches),
+        fill_fraction: fill_fraction(sub_matches),
+        header: writes_header(sub_matches),
+    }
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| String::from("expected key=value"))
+}
+
+fn lsb_stego(sub_matches: &ArgMatches) -> LsbStego {
+    LsbStego {
+        bits: sub_matches.get_one::<u8>("bits").copied(),
+        channels: channels(sub_matches),
+        region: sub_matches.get_one::<Region>("region").copied(),
+        lsb_matching: lsb_matching(sub_matches),
+        fill_fraction: fill_fraction(sub_matches),
+        header: writes_header(sub_matches),
+    }
+}
+
+fn extracting_stego(sub_matches: &ArgMatches) -> Box<dyn Steganographer> {
+    let lsb = lsb_stego(sub_matches);
+
+    if sub_matches.get_flag("ecc") {
+        Box::new(EccStego {
+            lsb,
+            ..EccStego::default()
+        })
+    } else {
+        Box::new(lsb)
+    }
+}
+
+fn check_lossless(path: &str) {
+    let is_webp = input_format(path) == Some(ImageFormat::WebP);
+
+    if is_webp && read_input(path).is_ok_and(|bytes| is_lossy_webp(&bytes)) {
+        fail_with(
+            "LossyWebp",
+            format!("{} is a lossy WebP, which cannot hold hidden data", path),
+            json!({ "input": path }),
+        );
+    }
+}
+
+// WebP and BMP only store 8-bit samples, so 16-bit carriers are converted
+// before hiding instead of losing the hidden bits when saving.
+fn carrier_for_output(image: DynamicImage, output: &str) -> DynamicImage {
+    let is_8_bit_output = matches!(
+        ImageFormat::from_path(output),
+        Ok(ImageFormat::WebP | ImageFormat::Bmp)
+    );
+
+    match image {
+        DynamicImage::ImageLuma16(_) if is_8_bit_output => {
+            DynamicImage::ImageLuma8(image.to_luma8())
+        }
+        DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgba16(_) if is_8_bit_output => {
+            DynamicImage::ImageRgba8(image.to_rgba8())
         }
+        DynamicImage::ImageRgb16(_) if is_8_bit_output => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    }
+}
+
+fn is_gif(path: &str) -> bool {
+    input_format(path) == Some(ImageFormat::Gif)
+}
+
+// GIF frames always use the default placement, which keeps every frame within
+// a 256-color palette.
+fn check_gif_options(sub_matches: &ArgMatches) {
+    let custom = [
+        "ecc",
+        "region",
+        "channels",
+        "alpha-only",
+        "bits",
+        "lsb-matching",
+        "fill-fraction",
+        "tag",
+    ]
+    .into_iter()
+    .find(|id| {
+        // Extraction commands have no --lsb-matching or --fill-fraction.
+        sub_matches.try_contains_id(id).unwrap_or(false)
+            && sub_matches.value_source(id) == Some(ValueSource::CommandLine)
+    });
+
+    if let Some(id) = custom {
+        fail_with(
+            "UnsupportedGifOption",
+            format!("--{} is not supported for GIF carriers", id),
+            json!({ "option": id }),
+        );
+    }
+}
+
+fn extract_text_payload(sub_matches: &ArgMatches) -> Result<Vec<u8>, SecretError> {
+    let image_path = sub_matches.get_one::<String>("image").unwrap();
+
+    if is_gif(image_path) {
+        check_gif_options(sub_matches);
+        let gif =
+            read_input(image_path).unwrap_or_else(|error| fail("Failed to open image", error));
+
+        return extract_bytes_from_gif(&gif);
     }
 
-    for (x, y, pixel) in hidden_buffer.enumerate_pixels() {
-        let mut normalized_pixel = Rgb([0u8; 3]);
+    check_lossless(image_path);
+
+    // PNG headers are read without decoding the whole image, so images that
+    // hold no payload are rejected early.
+    let is_png = ImageFormat::from_path(image_path).is_ok_and(|format| format == ImageFormat::Png);
+    if is_png && !is_stream_input(image_path) && !sub_matches.get_flag("ecc") {
+        let declared = read_declared_payload(
+            Path::new(image_path),
+            &embed_options(sub_matches),
+            max_pixels(),
+        )?;
 
-        for i in 0..3 {
-            let value = pixel[i];
-            let normalized_value =
-                ((value - min_value) as f32 / (max_value - min_value) as f32 * 255.0) as u8;
-            normalized_pixel[i] = normalized_value;
+        if !declared.fits() {
+            return Err(SecretError::DeclaredLengthExceedsCapacity {
+                declared: declared.len,
+                capacity: declared.capacity,
+            });
         }
+    }
+
+    let image =
+        open_image_source(image_path).unwrap_or_else(|error| fail("Failed to open image", error));
+    let stego = extracting_stego(sub_matches);
+
+    with_progress(|| match sub_matches.get_one::<String>("tag") {
+        Some(tag) => extract_tagged_payload(&*stego, &image, tag),
+        None => stego.extract(&image),
+    })
+}
 
-        normalized_buffer.put_pixel(x, y, normalized_pixel);
+fn collect_images(directory: &Path, recursive: bool, images: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if recursive {
+                collect_images(&path, recursive, images)?;
+            }
+        } else if ImageFormat::from_path(&path).is_ok_and(|format| format.reading_enabled()) {
+            images.push(path);
+        }
     }
 
-    DynamicImage::ImageRgb8(normalized_buffer)
+    Ok(())
 }
 
-fn expand_image(
-    source_image: &DynamicImage,
-    target_width: u32,
-    target_height: u32,
-) -> DynamicImage {
-    let (source_width, source_height) = source_image.dimensions();
+fn hide_text_in_directory(
+    image_dir: &Path,
+    output_dir: &Path,
+    sub_matches: &ArgMatches,
+    hide: impl Fn(&DynamicImage) -> Result<DynamicImage, SecretError>,
+) {
+    let force = sub_matches.get_flag("force");
+    let dry_run = sub_matches.get_flag("dry-run");
+
+    let mut image_paths = Vec::new();
+    collect_images(
+        image_dir,
+        sub_matches.get_flag("recursive"),
+        &mut image_paths,
+    )
+    .unwrap_or_else(|error| fail("Failed to read image directory", error));
+    image_paths.sort();
+
+    let images = image_paths.into_iter().map(|path| {
+        let image = open_image(&path, max_pixels());
+        (path, image)
+    });
 
-    let source_buffer = source_image.to_rgb8();
-    let mut expanded_buffer = ImageBuffer::new(target_width, target_height);
+    let mut succeeded = Vec::new();
+    let mut failed = Vec::new();
+    let mut outputs = HashSet::new();
 
-    for (x, y, pixel) in expanded_buffer.enumerate_pixels_mut() {
-        if x < source_width && y < source_height {
-            let source_pixel = source_buffer.get_pixel(x, y);
-            *pixel = *source_pixel;
-        } else {
-            *pixel = Rgb([0, 0, 0]);
+    // Outputs are always PNGs, whatever the input format, since a lossy
+    // format like JPEG would destroy the hidden bits when saving.
+    for (path, hidden_image) in hide_in_images(images, hide) {
+        let relative_path = path.strip_prefix(image_dir).unwrap();
+        let output_path = output_dir.join(relative_path).with_extension("png");
+
+        let result = hidden_image.and_then(|hidden_image| {
+            if !outputs.insert(output_path.clone()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "another input of the same name was already written to this output",
+                )
+                .into());
+            }
+
+            if !force && output_path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "the output already exists, pass --force to overwrite it",
+                )
+                .into());
+            }
+
+            if dry_run {
+                return Ok(());
+            }
+
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            save_hidden_image(
+                &hidden_image,
+                &output_path.to_string_lossy(),
+                &path.to_string_lossy(),
+                sub_matches,
+            )
+        });
+
+        match result {
+            Ok(()) => {
+                info!(
+                    "ok: {} -> {}",
+                    relative_path.display(),
+                    relative_path.with_extension("png").display()
+                );
+                succeeded.push(relative_path.to_path_buf());
+            }
+            Err(error) => {
+                error!("failed: {}: {}", relative_path.display(), error);
+                failed.push(relative_path.to_path_buf());
+            }
         }
     }
 
-    DynamicImage::ImageRgb8(expanded_buffer)
+    info!("{} succeeded, {} failed", succeeded.len(), failed.len());
+    print_json(json!({
+        "command": "hide_txt",
+        "dry_run": dry_run,
+        "succeeded": succeeded,
+        "failed": failed,
+    }));
+
+    if !failed.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn init_logger(matches: &ArgMatches) {
+    // With --json, the prose is left out unless asked for.
+    let level = if matches.get_flag("quiet") {
+        LevelFilter::Error
+    } else {
+        match matches.get_count("verbose") {
+            0 if matches.get_flag("json") => LevelFilter::Warn,
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(|buf, record| match record.level() {
+            log::Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level.as_str().to_lowercase(), record.args()),
+        })
+        .init();
 }
 
 fn main() {
@@ -244,14 +771,36 @@ fn main() {
         .author("lucin")
         .about("Hides and decrypts images")
         .arg_required_else_help(true)
+        .arg(arg!(-v --verbose ... "Shows more details, repeat for even more").global(true))
+        .arg(
+            arg!(-q --quiet "Only shows errors")
+                .global(true)
+                .conflicts_with("verbose"),
+        )
+        .arg(arg!(--force "Overwrites output files that already exist").global(true))
+        .arg(arg!(--json "Prints the result or the error as a JSON object on stdout").global(true))
+        .arg(
+            arg!(--"max-pixels" <PIXELS> "Refuses images with more pixels, 400 million by default, before decoding them")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .global(true),
+        )
+        .arg(
+            arg!(--"png-compression" <LEVEL> "Compression of PNG outputs, which are always encoded the same way")
+                .value_parser(["fast", "default", "best"])
+                .default_value("fast")
+                .global(true),
+        )
         .subcommand(
             Command::new("hide_img")
                 .about("Hides image")
-                .arg(arg!(--source <SOURCE>))
-                .arg(arg!(--secret <SECRET>))
-                .arg(arg!(--output <OUTPUT>))
-                .arg(arg!(--resize "Resizes the image"))
-                .arg(arg!(--expand "Expands the image"))
+                .args(hide_image_args())
+                .arg(
+                    arg!(--"target-psnr" <DB> "Uses the most bits that keep the carrier above this PSNR")
+                        .value_parser(clap::value_parser!(f64))
+                        .conflicts_with("bits"),
+                )
+                .arg(interleave_arg())
+                .arg(dry_run_arg())
                 .arg_required_else_help(true),
         )
         .subcommand(
@@ -259,6 +808,10 @@ fn main() {
                 .about("Decrypts image")
                 .arg(arg!(--source <SOURCE>))
                 .arg(arg!(--output <OUTPUT>))
+                .arg(bits_arg())
+                .arg(interleave_arg())
+                .arg(arg!(--dither "Smooths the banding of the recovered image with dithering"))
+                .arg(arg!(--"keep-carrier-size" "Keeps the carrier's size instead of restoring the secret's own"))
                 .arg_required_else_help(true),
         )
         .subcommand(
@@ -267,74 +820,806 @@ fn main() {
                 .arg(arg!(--image <IMAGE>))
                 .arg(arg!(--output <OUTPUT>))
                 .arg(arg!(--text <TEXT>...))
+                .arg(arg!(--recursive "Hides the text in images of nested directories too"))
+                .arg(dry_run_arg())
+                .arg(tag_arg())
+                .arg(
+                    arg!(--ecc <PARITY> "Adds Reed-Solomon parity bytes to every 255-byte block")
+                        .value_parser(clap::value_parser!(u8).range(2..=128))
+                        .num_args(0..=1)
+                        .default_missing_value("16"),
+                )
+                .args(text_placement_args())
+                .arg(lsb_matching_arg())
+                .arg(fill_fraction_arg())
+                .arg(no_header_arg())
+                .arg(keep_metadata_arg())
                 .arg_required_else_help(true),
         )
         .subcommand(
             Command::new("decrypt_txt")
                 .about("Decrypts text from an image")
                 .arg(arg!(--image <IMAGE>))
+                .arg(arg!(--output <OUTPUT> "Writes the text to a file, or to stdout with -"))
+                .arg(tag_arg())
+                .arg(arg!(--ecc "Corrects errors using the embedded Reed-Solomon parity"))
+                .args(text_placement_args())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Checks that the text hidden in an image can be recovered")
+                .arg(arg!(--image <IMAGE>))
+                .arg(arg!(--expect <TEXT> "The text that should be hidden"))
+                .arg(arg!(--"expect-file" <FILE> "Reads the expected payload from a file"))
+                .group(
+                    ArgGroup::new("expected")
+                        .args(["expect", "expect-file"])
+                        .required(true),
+                )
+                .arg(tag_arg())
+                .arg(arg!(--ecc "Corrects errors using the embedded Reed-Solomon parity"))
+                .args(text_placement_args())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("hide_meta")
+                .about("Hides key=value metadata in an image as JSON")
+                .arg(arg!(--image <IMAGE>))
+                .arg(arg!(--output <OUTPUT>))
+                .arg(
+                    arg!(--set <KEY_VALUE>... "Metadata entry, can be repeated")
+                        .value_parser(parse_key_value)
+                        .required(true),
+                )
+                .args(text_placement_args())
+                .arg(lsb_matching_arg())
+                .arg(fill_fraction_arg())
+                .arg(no_header_arg())
+                .arg(keep_metadata_arg())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("extract_meta")
+                .about("Prints the metadata hidden with hide_meta")
+                .arg(arg!(--image <IMAGE>))
+                .args(text_placement_args())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("hide_both")
+                .about("Hides an image and text in the same image")
+                .args(hide_image_args())
+                .arg(arg!(--text <TEXT>))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("extract_both")
+                .about("Decrypts an image and text hidden with hide_both")
+                .arg(arg!(--source <SOURCE>))
+                .arg(arg!(--output <OUTPUT>))
+                .arg(bits_arg())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("hide_file_split")
+                .about("Hides a file split across several images")
+                .arg(arg!(--file <FILE>))
+                .arg(arg!(--carriers <CARRIERS>...).num_args(1..))
+                .arg(arg!(--output <OUTPUT> "Directory for the hidden images"))
+                .arg(keep_metadata_arg())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("extract_file_join")
+                .about("Extracts a file split across several images")
+                .arg(arg!(--images <IMAGES>...).num_args(1..))
+                .arg(arg!(--output <OUTPUT> "Writes the file to a path, or to stdout with -"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("capacity")
+                .about("Shows how many bytes an image can hold and the length it declares")
+                .arg(arg!(--image <IMAGE>))
+                .args(text_placement_args())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Highlights the pixels changed between two images")
+                .arg(arg!(--original <ORIGINAL>))
+                .arg(arg!(--stego <STEGO>))
+                .arg(arg!(--output <OUTPUT>))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("bitplane")
+                .about("Renders one bit plane of an image in black and white")
+                .arg(arg!(--image <IMAGE>))
+                .arg(
+                    arg!(--channel <CHANNEL>)
+                        .value_parser(["r", "g", "b", "a"])
+                        .default_value("r"),
+                )
+                .arg(
+                    arg!(--bit <BIT> "Bit to render, 0 being the least significant")
+                        .value_parser(clap::value_parser!(u8).range(0..16))
+                        .default_value("0"),
+                )
+                .arg(arg!(--output <OUTPUT>))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("strip")
+                .about("Overwrites the low bits of an image with noise to destroy any hidden data")
+                .arg(arg!(--image <IMAGE>))
+                .arg(arg!(--output <OUTPUT>))
+                .arg(
+                    arg!(--bits <BITS> "Low bits of each sample to overwrite")
+                        .value_parser(clap::value_parser!(u8).range(1..=8))
+                        .default_value("2"),
+                )
                 .arg_required_else_help(true),
         )
         .get_matches();
 
+    init_logger(&matches);
+    JSON_OUTPUT.set(matches.get_flag("json")).unwrap();
+    SHOW_PROGRESS
+        .set(io::stderr().is_terminal() && !matches.get_flag("quiet") && !json_output())
+        .unwrap();
+    MAX_PIXELS
+        .set(
+            matches
+                .get_one::<u64>("max-pixels")
+                .copied()
+                .unwrap_or(DEFAULT_MAX_PIXELS),
+        )
+        .unwrap();
+
     match matches.subcommand() {
         Some(("hide_img", sub_matches)) => {
             let source = sub_matches.get_one::<String>("source").unwrap();
             let secret = sub_matches.get_one::<String>("secret").unwrap();
             let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+            let mut options = hide_image_options(sub_matches);
+            options.interleave = sub_matches.get_flag("interleave");
+            let source_image = open_source_image(source, sub_matches);
+            let secret_image = open_image_source(secret)
+                .unwrap_or_else(|error| fail("Failed to open secret image", error));
 
-            let resize = sub_matches.get_flag("resize");
-            let expand = sub_matches.get_flag("expand");
+            // Bits that differ per channel are easy to get wrong when
+            // decrypting, and the size of a secret that had to fit the
+            // carrier is lost without the header, so both are always recorded.
+            options.header = !options.bits.is_uniform()
+                || secret_shape(
+                    source_image.dimensions(),
+                    secret_image.dimensions(),
+                    &options,
+                )
+                .is_some();
 
-            let source_image =
-                image::open(&Path::new(&source)).expect("Failed to open source image");
-            let secret_image =
-                image::open(&Path::new(&secret)).expect("Failed to open secret image");
+            if let Some(&target_psnr) = sub_matches.get_one::<f64>("target-psnr") {
+                let selected =
+                    select_image_bits(&source_image, &secret_image, &options, target_psnr)
+                        .unwrap_or_else(|error| fail("Failed to select the number of bits", error));
 
-            let normalized_image = normalize_image(&source_image);
-            let hidden_image = hide_image(&normalized_image, &secret_image, resize, expand);
+                let Some((bits, hidden_psnr)) = selected else {
+                    fail_with(
+                        "TargetPsnrUnreachable",
+                        format!("Even 1 bit brings the carrier below {} dB", target_psnr),
+                        json!({ "target_psnr": target_psnr }),
+                    );
+                };
+
+                info!("Using {} bits for a PSNR of {:.2} dB", bits, hidden_psnr);
+                options.bits = ImageBits::uniform(bits);
+                options.header = true;
+            }
 
-            hidden_image
-                .save(&Path::new(&output))
-                .expect("Failed to save hidden image");
+            if sub_matches.get_flag("dry-run") {
+                let plan = plan_image_fit(
+                    source_image.dimensions(),
+                    secret_image.dimensions(),
+                    &options,
+                );
+                let sample_bytes = |(width, height): (u32, u32), bits: ImageBits| {
+                    width as u64 * height as u64 * bits.total() as u64 / 8
+                };
+                let secret_bits = if options.interleave {
+                    options.bits.doubled()
+                } else {
+                    options.bits
+                };
+                let needed = sample_bytes(secret_image.dimensions(), secret_bits);
+                let available = sample_bytes((plan.width, plan.height), options.bits);
 
-            println!("Image hidden successfully");
+                info!(
+                    "Secret: {} bytes needed at {} bits per channel, {} available",
+                    needed, secret_bits, available
+                );
+                info!(
+                    "Fit: {:?} ({}x{} source, {}x{} secret)",
+                    plan.fit,
+                    source_image.width(),
+                    source_image.height(),
+                    secret_image.width(),
+                    secret_image.height()
+                );
+                info!(
+                    "Output: {}",
+                    describe_output(output, plan.width, plan.height, ColorType::Rgb8)
+                );
+                info!("Dry run, nothing was written");
+                print_json(json!({
+                    "command": "hide_img",
+                    "dry_run": true,
+                    "needed": needed,
+                    "available": available,
+                    "fit": format!("{:?}", plan.fit),
+                    "width": plan.width,
+                    "height": plan.height,
+                    "output": output,
+                }));
+                return;
+            }
+
+            let hidden_image = with_progress(|| hide_image(&source_image, &secret_image, &options))
+                .unwrap_or_else(|error| fail("Failed to hide image", error));
+
+            save_hidden_image(&hidden_image, output, source, sub_matches)
+                .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+
+            info!("Image hidden successfully");
+            print_json(json!({
+                "command": "hide_img",
+                "output": output,
+                "bits": options.bits.to_string(),
+                "width": hidden_image.width(),
+                "height": hidden_image.height(),
+            }));
         }
         Some(("decrypt_img", sub_matches)) => {
             let source = sub_matches.get_one::<String>("source").unwrap();
             let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
 
-            let hidden_image =
-                image::open(&Path::new(&source)).expect("Failed to open hidden image");
+            check_lossless(source);
+            let hidden_image = open_image_source(source)
+                .unwrap_or_else(|error| fail("Failed to open hidden image", error));
 
-            let decrypted_image = decrypt_image(&hidden_image);
-            decrypted_image
-                .save(&Path::new(&output))
-                .expect("Failed to save decrypted image");
+            let (bits, interleaved, shape) = match read_image_header(&hidden_image, 0) {
+                Some(header) => {
+                    info!("Using {} bits from the image header", header.bits);
+                    (header.bits, header.interleaved, header.shape)
+                }
+                None => (
+                    *sub_matches.get_one::<ImageBits>("bits").unwrap(),
+                    sub_matches.get_flag("interleave"),
+                    None,
+                ),
+            };
 
-            println!("Image decrypted successfully");
+            let dither = sub_matches.get_flag("dither");
+
+            let decrypted_image =
+                with_progress(|| reconstruct(&hidden_image, bits, interleaved, dither))
+                    .unwrap_or_else(|error| fail("Failed to decrypt image", error));
+            let decrypted_image = match shape {
+                Some(shape) if !sub_matches.get_flag("keep-carrier-size") => {
+                    let (width, height) = shape.dimensions();
+                    check_dimensions(width, height, max_pixels())
+                        .unwrap_or_else(|error| fail("Failed to restore the secret's size", error));
+
+                    info!(
+                        "Restoring the secret's original size of {}x{}",
+                        width, height
+                    );
+                    restore_secret_shape(&decrypted_image, shape, FilterType::Lanczos3)
+                }
+                _ => decrypted_image,
+            };
+            save_image(&decrypted_image, output, png_compression(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to save decrypted image", error));
+
+            info!("Image decrypted successfully");
+            print_json(json!({
+                "command": "decrypt_img",
+                "output": output,
+                "bits": bits.to_string(),
+                "interleaved": interleaved,
+                "width": decrypted_image.width(),
+                "height": decrypted_image.height(),
+            }));
         }
         Some(("hide_txt", sub_matches)) => {
             let image_path = sub_matches.get_one::<String>("image").unwrap();
             let text = sub_matches.get_one::<String>("text").unwrap();
             let output_path = sub_matches.get_one::<String>("output").unwrap();
+            let lsb = lsb_stego(sub_matches);
 
-            let image = image::open(&Path::new(&image_path)).expect("Failed to open image");
+            let stego: Box<dyn Steganographer> = match sub_matches.get_one::<u8>("ecc") {
+                Some(&parity) => Box::new(EccStego { parity, lsb }),
+                None => Box::new(lsb),
+            };
+            let tag = sub_matches.get_one::<String>("tag");
 
-            let hidden_image = hide_text_in_image(&image, &text);
+            // A tagged text is added to the table of tagged texts already in
+            // the carrier, which is then hidden as the payload.
+            let payload = |image: &DynamicImage| match tag {
+                Some(tag) => append_tagged_payload(&*stego, image, tag, text.as_bytes()),
+                None => Ok(text.as_bytes().to_vec()),
+            };
+            let hide = |image: &DynamicImage| stego.embed(image, &payload(image)?);
+
+            if Path::new(image_path).is_dir() {
+                hide_text_in_directory(
+                    Path::new(image_path),
+                    Path::new(output_path),
+                    sub_matches,
+                    hide,
+                );
+                return;
+            }
 
-            hidden_image
-                .save(&Path::new(&output_path))
-                .expect("Failed to save hidden image");
+            check_output(output_path, sub_matches.get_flag("force"));
 
-            println!("Text hidden successfully");
+            if is_gif(image_path) {
+                check_gif_options(sub_matches);
+                let gif = read_input(image_path)
+                    .unwrap_or_else(|error| fail("Failed to open image", error));
+
+                let stego = hide_bytes_in_gif(&gif, text.as_bytes())
+                    .unwrap_or_else(|error| fail("Failed to hide text", error));
+
+                let dry_run = sub_matches.get_flag("dry-run");
+
+                if dry_run {
+                    info!("Output: {} ({} bytes GIF)", output_path, stego.len());
+                    info!("Dry run, nothing was written");
+                } else {
+                    write_output(&stego, output_path)
+                        .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+                    info!("Text hidden successfully");
+                }
+
+                print_json(json!({
+                    "command": "hide_txt",
+                    "dry_run": dry_run,
+                    "output": output_path,
+                    "bytes": text.len(),
+                }));
+                return;
+            }
+
+            let image = open_image_source(image_path)
+                .unwrap_or_else(|error| fail("Failed to open image", error));
+            let image = carrier_for_output(image, output_path);
+            let payload =
+                payload(&image).unwrap_or_else(|error| fail("Failed to read tagged texts", error));
+
+            if sub_matches.get_flag("dry-run") {
+                let capacity = stego
+                    .capacity(&image)
+                    .unwrap_or_else(|error| fail("Failed to compute the capacity", error));
+
+                info!(
+                    "Payload: {} bytes needed, {} available",
+                    payload.len(),
+                    capacity
+                );
+                info!(
+                    "Output: {}",
+                    describe_output(output_path, image.width(), image.height(), image.color())
+                );
+
+                if payload.len() > capacity {
+                    fail(
+                        &format!("The text does not fit in {}", image_path),
+                        SecretError::InsufficientCapacity {
+                            needed: payload.len(),
+                            available: capacity,
+                        },
+                    );
+                }
+
+                info!("Dry run, nothing was written");
+                print_json(json!({
+                    "command": "hide_txt",
+                    "dry_run": true,
+                    "output": output_path,
+                    "bytes": payload.len(),
+                    "capacity": capacity,
+                }));
+                return;
+            }
+
+            let (hidden_image, report) =
+                with_progress(|| stego.embed_with_report(&image, &payload))
+                    .unwrap_or_else(|error| fail("Failed to hide text", error));
+
+            save_hidden_image(&hidden_image, output_path, image_path, sub_matches)
+                .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+
+            info!(
+                "Embedded {} bytes using {:.1}% of capacity ({} pixels)",
+                report.bytes_written,
+                report.capacity_used(),
+                report.pixels_used
+            );
+            info!("Text hidden successfully");
+            print_json(json!({
+                "command": "hide_txt",
+                "output": output_path,
+                "bytes": report.bytes_written,
+                "capacity": report.capacity,
+                "pixels": report.pixels_used,
+            }));
         }
         Some(("decrypt_txt", sub_matches)) => {
+            let payload = extract_text_payload(sub_matches)
+                .unwrap_or_else(|error| fail("Failed to decrypt text", error));
+            info!("Extracted {} bytes", payload.len());
+
+            // The output gets the payload as it is, while printed text must
+            // be UTF-8, as it must for extract_text_from_image.
+            if let Some(output) = sub_matches.get_one::<String>("output") {
+                check_output(output, sub_matches.get_flag("force"));
+                write_output(&payload, output)
+                    .unwrap_or_else(|error| fail("Failed to write text", error));
+                info!("Text decrypted successfully");
+                print_json(json!({
+                    "command": "decrypt_txt",
+                    "output": output,
+                    "bytes": payload.len(),
+                }));
+                return;
+            }
+
+            let extracted_text = String::from_utf8(payload)
+                .unwrap_or_else(|error| fail("Failed to decrypt text", SecretError::from(error)));
+            if json_output() {
+                print_json(json!({ "command": "decrypt_txt", "text": extracted_text }));
+            } else {
+                println!("Extracted Text: {}", extracted_text);
+            }
+        }
+        Some(("verify", sub_matches)) => {
+            let expected = match sub_matches.get_one::<String>("expect") {
+                Some(text) => text.as_bytes().to_vec(),
+                None => {
+                    let expect_path = sub_matches.get_one::<String>("expect-file").unwrap();
+                    fs::read(expect_path)
+                        .unwrap_or_else(|error| fail("Failed to read expected file", error))
+                }
+            };
+
+            let payload = extract_text_payload(sub_matches)
+                .unwrap_or_else(|error| fail("Failed to extract payload", error));
+
+            if payload == expected {
+                info!("Payload matches ({} bytes)", payload.len());
+                print_json(json!({
+                    "command": "verify",
+                    "matches": true,
+                    "bytes": payload.len(),
+                }));
+            } else {
+                let first_difference = payload
+                    .iter()
+                    .zip(&expected)
+                    .position(|(actual, expected)| actual != expected)
+                    .unwrap_or(payload.len().min(expected.len()));
+
+                fail_with(
+                    "PayloadMismatch",
+                    format!(
+                        "Payload does not match: expected {} bytes, extracted {} bytes, first difference at byte {}",
+                        expected.len(),
+                        payload.len(),
+                        first_difference
+                    ),
+                    json!({
+                        "expected": expected.len(),
+                        "actual": payload.len(),
+                        "first_difference": first_difference,
+                    }),
+                );
+            }
+        }
+        Some(("hide_meta", sub_matches)) => {
+            let image_path = sub_matches.get_one::<String>("image").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+
+            let meta: Metadata = sub_matches
+                .get_many::<(String, String)>("set")
+                .unwrap()
+                .cloned()
+                .collect();
+
+            let image = open_image_source(image_path)
+                .unwrap_or_else(|error| fail("Failed to open image", error));
+            let image = carrier_for_output(image, output);
+
+            let hidden_image = hide_meta_in_image(&image, &meta, &embed_options(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to hide metadata", error));
+
+            save_hidden_image(&hidden_image, output, image_path, sub_matches)
+                .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+
+            info!("Metadata hidden successfully");
+            print_json(json!({
+                "command": "hide_meta",
+                "output": output,
+                "entries": meta.len(),
+            }));
+        }
+        Some(("extract_meta", sub_matches)) => {
+            let image_path = sub_matches.get_one::<String>("image").unwrap();
+            check_lossless(image_path);
+            let image = open_image_source(image_path)
+                .unwrap_or_else(|error| fail("Failed to open image", error));
+
+            let meta = extract_meta_from_image(&image, &embed_options(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to extract metadata", error));
+
+            if json_output() {
+                print_json(json!({ "command": "extract_meta", "metadata": meta }));
+            } else {
+                println!("{}", serde_json::to_string_pretty(&meta).unwrap());
+            }
+        }
+        Some(("hide_both", sub_matches)) => {
+            let source = sub_matches.get_one::<String>("source").unwrap();
+            let secret = sub_matches.get_one::<String>("secret").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+            let text = sub_matches.get_one::<String>("text").unwrap();
+            let options = hide_image_options(sub_matches);
+
+            let source_image = open_source_image(source, sub_matches);
+            let secret_image = open_image_source(secret)
+                .unwrap_or_else(|error| fail("Failed to open secret image", error));
+            let hidden_image =
+                with_progress(|| hide_image_and_text(&source_image, &secret_image, text, &options))
+                    .unwrap_or_else(|error| fail("Failed to hide text", error));
+
+            save_hidden_image(&hidden_image, output, source, sub_matches)
+                .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+
+            info!("Image and text hidden successfully");
+            print_json(json!({ "command": "hide_both", "output": output }));
+        }
+        Some(("extract_both", sub_matches)) => {
+            let source = sub_matches.get_one::<String>("source").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+            let bits = *sub_matches.get_one::<ImageBits>("bits").unwrap();
+
+            check_lossless(source);
+            let hidden_image = open_image_source(source)
+                .unwrap_or_else(|error| fail("Failed to open hidden image", error));
+
+            let (decrypted_image, extracted_text) =
+                with_progress(|| extract_image_and_text(&hidden_image, bits))
+                    .unwrap_or_else(|error| fail("Failed to extract text", error));
+            save_image(&decrypted_image, output, png_compression(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to save decrypted image", error));
+
+            if json_output() {
+                print_json(json!({
+                    "command": "extract_both",
+                    "output": output,
+                    "text": extracted_text,
+                }));
+            } else if output == STDOUT_PATH {
+                eprintln!("Extracted Text: {}", extracted_text);
+            } else {
+                println!("Extracted Text: {}", extracted_text);
+            }
+        }
+        Some(("hide_file_split", sub_matches)) => {
+            let file_path = sub_matches.get_one::<String>("file").unwrap();
+            let carrier_paths: Vec<&String> = sub_matches.get_many("carriers").unwrap().collect();
+            let output_dir = Path::new(sub_matches.get_one::<String>("output").unwrap());
+
+            let payload =
+                fs::read(file_path).unwrap_or_else(|error| fail("Failed to read file", error));
+            let carriers: Vec<DynamicImage> = carrier_paths
+                .iter()
+                .map(|path| {
+                    open_image_source(path)
+                        .unwrap_or_else(|error| fail("Failed to open carrier image", error))
+                })
+                .collect();
+
+            let hidden_images = hide_bytes_across_images(&carriers, &payload)
+                .unwrap_or_else(|error| fail("Failed to hide file", error));
+
+            // Only the carriers holding a chunk are written.
+            let outputs: Vec<(PathBuf, &String, DynamicImage)> = carrier_paths
+                .iter()
+                .zip(hidden_images)
+                .filter_map(|(carrier_path, hidden_image)| {
+                    let file_name = Path::new(carrier_path).with_extension("png");
+                    Some((
+                        output_dir.join(file_name.file_name().unwrap()),
+                        *carrier_path,
+                        hidden_image?,
+                    ))
+                })
+                .collect();
+            let output_paths: Vec<&PathBuf> = outputs.iter().map(|(path, _, _)| path).collect();
+
+            // Carriers of the same name in different directories would be
+            // saved over each other.
+            let mut seen = HashSet::new();
+            for output_path in &output_paths {
+                if !seen.insert(output_path) {
+                    fail_with(
+                        "DuplicateOutput",
+                        format!(
+                            "two carriers would both be saved as {}, rename one of them",
+                            output_path.display()
+                        ),
+                        json!({ "output": output_path }),
+                    );
+                }
+
+                check_output(
+                    &output_path.to_string_lossy(),
+                    sub_matches.get_flag("force"),
+                );
+            }
+
+            fs::create_dir_all(output_dir)
+                .unwrap_or_else(|error| fail("Failed to create output directory", error));
+
+            for (output_path, carrier_path, hidden_image) in &outputs {
+                save_hidden_image(
+                    hidden_image,
+                    &output_path.to_string_lossy(),
+                    carrier_path,
+                    sub_matches,
+                )
+                .unwrap_or_else(|error| fail("Failed to save hidden image", error));
+            }
+
+            info!("File hidden, {} images written", outputs.len());
+            print_json(json!({
+                "command": "hide_file_split",
+                "outputs": output_paths,
+                "bytes": payload.len(),
+            }));
+        }
+        Some(("extract_file_join", sub_matches)) => {
+            let image_paths = sub_matches.get_many::<String>("images").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+
+            let images: Vec<DynamicImage> = image_paths
+                .map(|path| {
+                    check_lossless(path);
+                    open_image_source(path)
+                        .unwrap_or_else(|error| fail("Failed to open image", error))
+                })
+                .collect();
+
+            let payload = join_bytes_from_images(&images)
+                .unwrap_or_else(|error| fail("Failed to extract file", error));
+
+            write_output(&payload, output)
+                .unwrap_or_else(|error| fail("Failed to write file", error));
+
+            info!("File extracted successfully");
+            print_json(json!({
+                "command": "extract_file_join",
+                "output": output,
+                "bytes": payload.len(),
+            }));
+        }
+        Some(("bitplane", sub_matches)) => {
+            let image_path = sub_matches.get_one::<String>("image").unwrap();
+            let bit = *sub_matches.get_one::<u8>("bit").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+
+            let channel = match sub_matches.get_one::<String>("channel").unwrap().as_str() {
+                "r" => 0,
+                "g" => 1,
+                "b" => 2,
+                "a" => 3,
+                _ => unreachable!(),
+            };
+
+            let image = open_image_source(image_path)
+                .unwrap_or_else(|error| fail("Failed to open image", error));
+            let plane_image = extract_bit_plane(&image, channel, bit)
+                .unwrap_or_else(|error| fail("Failed to extract bit plane", error));
+
+            save_image(&plane_image, output, png_compression(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to save bit plane", error));
+
+            info!("Bit plane saved successfully");
+            print_json(json!({ "command": "bitplane", "output": output }));
+        }
+        Some(("capacity", sub_matches)) => {
             let image_path = sub_matches.get_one::<String>("image").unwrap();
-            let image = image::open(&Path::new(&image_path)).expect("Failed to open image");
-            let extracted_text = extract_text_from_image(&image);
 
-            println!("Extracted Text: {}", extracted_text);
+            let declared = if is_stream_input(image_path) {
+                open_image_source(image_path)
+                    .and_then(|image| declared_payload(&image, &embed_options(sub_matches)))
+            } else {
+                read_declared_payload(
+                    Path::new(image_path),
+                    &embed_options(sub_matches),
+                    max_pixels(),
+                )
+            }
+            .unwrap_or_else(|error| fail("Failed to read image", error));
+
+            info!("Capacity: {} bytes", declared.capacity);
+
+            if declared.fits() {
+                info!("Declared payload: {} bytes", declared.len);
+            } else {
+                info!("No payload found");
+            }
+
+            print_json(json!({
+                "command": "capacity",
+                "capacity": declared.capacity,
+                "declared": declared.fits().then_some(declared.len),
+            }));
+        }
+        Some(("diff", sub_matches)) => {
+            let original = sub_matches.get_one::<String>("original").unwrap();
+            let stego = sub_matches.get_one::<String>("stego").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+
+            let original_image = open_image_source(original)
+                .unwrap_or_else(|error| fail("Failed to open original image", error));
+            let stego_image = open_image_source(stego)
+                .unwrap_or_else(|error| fail("Failed to open stego image", error));
+
+            let (diff_image, changed_pixels) = diff_images(&original_image, &stego_image)
+                .unwrap_or_else(|error| fail("Failed to compare images", error));
+
+            save_image(&diff_image, output, png_compression(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to save diff image", error));
+
+            let total_pixels = diff_image.width() as usize * diff_image.height() as usize;
+            info!(
+                "{} of {} pixels changed ({:.2}%)",
+                changed_pixels,
+                total_pixels,
+                changed_pixels as f64 / total_pixels.max(1) as f64 * 100.0
+            );
+            print_json(json!({
+                "command": "diff",
+                "output": output,
+                "changed_pixels": changed_pixels,
+                "total_pixels": total_pixels,
+            }));
+        }
+        Some(("strip", sub_matches)) => {
+            let image_path = sub_matches.get_one::<String>("image").unwrap();
+            let bits = *sub_matches.get_one::<u8>("bits").unwrap();
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            check_output(output, sub_matches.get_flag("force"));
+
+            let image = open_image_source(image_path)
+                .unwrap_or_else(|error| fail("Failed to open image", error));
+            let stripped_image = strip_lsb(&image, bits);
+
+            save_image(&stripped_image, output, png_compression(sub_matches))
+                .unwrap_or_else(|error| fail("Failed to save stripped image", error));
+
+            info!("Stripped the lowest {} bits of every sample", bits);
+            print_json(json!({ "command": "strip", "output": output, "bits": bits }));
         }
         _ => unreachable!(),
     }
@@ -0,0 +1,54 @@
+use std::io::Cursor;
+
+use image::ImageFormat;
+
+use crate::{
+    extract_bytes_from_image, hide_bytes_in_image, load_image, EmbedOptions, SecretError,
+    DEFAULT_MAX_PIXELS,
+};
+
+// Only formats that are always written losslessly keep the hidden bits, and
+// the WebP encoder only writes lossless files.
+pub fn hide_payload_bytes(
+    carrier: &[u8],
+    payload: &[u8],
+    format: ImageFormat,
+) -> Result<Vec<u8>, SecretError> {
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Bmp | ImageFormat::WebP
+    ) {
+        return Err(SecretError::LossyFormat(format));
+    }
+
+    let image = load_image(carrier, DEFAULT_MAX_PIXELS)?;
+    let options = EmbedOptions {
+        header: true,
+        ..EmbedOptions::default()
+    };
+    let hidden_image = hide_bytes_in_image(&image, payload, &options)?;
+
+    let mut stego = Cursor::new(Vec::new());
+    hidden_image.write_to(&mut stego, format)?;
+
+    Ok(stego.into_inner())
+}
+
+pub fn extract_payload_bytes(stego: &[u8]) -> Result<Vec<u8>, SecretError> {
+    let image = load_image(stego, DEFAULT_MAX_PIXELS)?;
+    extract_bytes_from_image(&image, &EmbedOptions::default())
+}
+
+pub fn hide_text_bytes(
+    carrier: &[u8],
+    text: &str,
+    format: ImageFormat,
+) -> Result<Vec<u8>, SecretError> {
+    hide_payload_bytes(carrier, text.as_bytes(), format)
+}
+
+pub fn extract_text_bytes(stego: &[u8]) -> Result<String, SecretError> {
+    let payload = extract_payload_bytes(stego)?;
+
+    Ok(String::from_utf8(payload)?)
+}
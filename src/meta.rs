@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+
+use image::DynamicImage;
+
+use crate::{
+    extract_bytes_from_image, hide_payload_in_image, mode::find_mode_header, EmbedOptions,
+    PayloadKind, SecretError,
+};
+
+pub type Metadata = BTreeMap<String, String>;
+
+// The metadata is stored as a JSON object through the regular payload path,
+// and marked as metadata in the mode header when there is one.
+pub fn hide_meta_in_image(
+    image: &DynamicImage,
+    meta: &Metadata,
+    options: &EmbedOptions,
+) -> Result<DynamicImage, SecretError> {
+    let payload = serde_json::to_vec(meta)?;
+    hide_payload_in_image(image, &payload, options, PayloadKind::Metadata)
+        .map(|(hidden_image, _)| hidden_image)
+}
+
+pub fn extract_meta_from_image(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<Metadata, SecretError> {
+    if let Some(header) = find_mode_header(image, options.region) {
+        if header.kind != PayloadKind::Metadata {
+            return Err(SecretError::NotMetadata(header.kind));
+        }
+    }
+
+    let payload = extract_bytes_from_image(image, options)?;
+    Ok(serde_json::from_slice(&payload)?)
+}
@@ -0,0 +1,371 @@
+use std::fmt;
+
+use image::{ColorType, DynamicImage, GenericImageView};
+use log::debug;
+use reed_solomon::{Decoder, Encoder};
+
+use crate::{
+    ecc::{ecc_capacity, ecc_placement_for, extract_ecc_bytes},
+    embed_stream, extract_lsb_bytes, extract_stream, is_grayscale, payload_room, stream_capacity,
+    Channels, DeclaredPayload, EmbedOptions, Placement, Region, SecretError,
+};
+
+// The mode header describes how the payload was hidden, so that it can be
+// extracted without any options. It is stored MSB-first in the lowest bit of
+// the first samples the payload would use, inside its region and channels,
+// and the payload starts after them. It is laid out as the magic number, the
+// version, the bits per sample, the channel mask, the ECC parity (0 without
+// ECC), the flags, which also tell what kind of payload follows, the region if
+// the flags say so, the payload length and a
+// CRC-32 of everything before it. With ECC, Reed-Solomon parity follows, so
+// that the noise the payload survives does not lose the header.
+const MODE_MAGIC: u16 = 0x534D;
+const MODE_VERSION: u8 = 1;
+const MODE_FIELDS_LEN: usize = 7;
+const MODE_REGION_LEN: usize = 16;
+const MODE_TRAILER_LEN: usize = 8;
+const MODE_PARITY: usize = 16;
+
+const FLAG_COMPRESSED: u8 = 0b001;
+const FLAG_ENCRYPTED: u8 = 0b010;
+const FLAG_REGION: u8 = 0b100;
+const FLAG_METADATA: u8 = 0b1000;
+const FLAG_CHUNK: u8 = 0b10000;
+
+// What the payload holds, so that it can be told apart from the payloads of
+// other commands without parsing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadKind {
+    #[default]
+    Bytes,
+    Metadata,
+    // One chunk of a payload split across images.
+    Chunk,
+}
+
+impl fmt::Display for PayloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadKind::Bytes => write!(f, "bytes"),
+            PayloadKind::Metadata => write!(f, "metadata"),
+            PayloadKind::Chunk => write!(f, "a chunk of a split payload"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModeHeader {
+    pub bits: u8,
+    pub channels: Channels,
+    pub region: Option<Region>,
+    pub parity: u8,
+    pub compressed: bool,
+    pub encrypted: bool,
+    pub kind: PayloadKind,
+    pub payload_len: u32,
+}
+
+fn mode_header_len(has_region: bool) -> usize {
+    let region_len = if has_region { MODE_REGION_LEN } else { 0 };
+    MODE_FIELDS_LEN + region_len + MODE_TRAILER_LEN
+}
+
+fn protected_len(has_region: bool, protected: bool) -> usize {
+    let parity_len = if protected { MODE_PARITY } else { 0 };
+    mode_header_len(has_region) + parity_len
+}
+
+pub(crate) fn mode_header_samples(has_region: bool, protected: bool) -> usize {
+    protected_len(has_region, protected) * 8
+}
+
+// The header uses one bit of each sample in the region and channels of the
+// payload, so that pixels and channels outside them stay untouched.
+fn header_placement(
+    width: u32,
+    height: u32,
+    color: ColorType,
+    channels: Channels,
+    region: Option<Region>,
+) -> Placement {
+    let mut placement = match region {
+        Some(region) => Placement::new(color, region, channels),
+        None => Placement::full(width, height, color, channels),
+    };
+    placement.bits = 1;
+    placement
+}
+
+impl ModeHeader {
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.encrypted {
+            flags |= FLAG_ENCRYPTED;
+        }
+        if self.region.is_some() {
+            flags |= FLAG_REGION;
+        }
+        match self.kind {
+            PayloadKind::Bytes => {}
+            PayloadKind::Metadata => flags |= FLAG_METADATA,
+            PayloadKind::Chunk => flags |= FLAG_CHUNK,
+        }
+
+        flags
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = MODE_MAGIC.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[
+            MODE_VERSION,
+            self.bits,
+            self.channels.bits(),
+            self.parity,
+            self.flags(),
+        ]);
+
+        if let Some(region) = self.region {
+            for value in [region.x, region.y, region.width, region.height] {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&self.payload_len.to_be_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(mut bytes: impl Iterator<Item = u8>) -> Option<Self> {
+        let mut read = |len: usize, header: &mut Vec<u8>| {
+            let start = header.len();
+            header.extend(bytes.by_ref().take(len));
+            (header.len() == start + len).then(|| header[start..].to_vec())
+        };
+        let u32_at = |bytes: &[u8], index: usize| {
+            u32::from_be_bytes(bytes[index..index + 4].try_into().unwrap())
+        };
+
+        let mut header = Vec::new();
+        let fields = read(MODE_FIELDS_LEN, &mut header)?;
+
+        if u16::from_be_bytes([fields[0], fields[1]]) != MODE_MAGIC || fields[2] != MODE_VERSION {
+            return None;
+        }
+
+        let [bits, channels, parity, flags] = [fields[3], fields[4], fields[5], fields[6]];
+        let known_flags =
+            FLAG_COMPRESSED | FLAG_ENCRYPTED | FLAG_REGION | FLAG_METADATA | FLAG_CHUNK;
+        if flags & !known_flags != 0 {
+            return None;
+        }
+        let kind = match (flags & FLAG_METADATA != 0, flags & FLAG_CHUNK != 0) {
+            (false, false) => PayloadKind::Bytes,
+            (true, false) => PayloadKind::Metadata,
+            (false, true) => PayloadKind::Chunk,
+            (true, true) => return None,
+        };
+
+        let region = if flags & FLAG_REGION != 0 {
+            let region = read(MODE_REGION_LEN, &mut header)?;
+            Some(Region {
+                x: u32_at(&region, 0),
+                y: u32_at(&region, 4),
+                width: u32_at(&region, 8),
+                height: u32_at(&region, 12),
+            })
+        } else {
+            None
+        };
+
+        let payload_len = u32_at(&read(4, &mut header)?, 0);
+        let checksum = u32_at(&read(4, &mut header)?, 0);
+
+        if crc32fast::hash(&header[..header.len() - 4]) != checksum {
+            return None;
+        }
+
+        Some(Self {
+            bits,
+            channels: Channels::from_bits(channels)?,
+            region,
+            parity,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            kind,
+            payload_len,
+        })
+    }
+
+    // The options the payload was hidden with.
+    pub fn options(&self) -> EmbedOptions {
+        EmbedOptions {
+            region: self.region,
+            channels: self.channels,
+            bits: Some(self.bits),
+            header: true,
+            ..EmbedOptions::default()
+        }
+    }
+
+    pub(crate) fn declared(
+        &self,
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> Result<DeclaredPayload, SecretError> {
+        let options = self.options();
+
+        let capacity = if self.parity == 0 {
+            payload_room(stream_capacity(
+                &options.placement_for(width, height, color)?,
+            ))
+        } else {
+            let placement = ecc_placement_for(&options, width, height, color)?;
+            ecc_capacity(stream_capacity(&placement), self.parity)
+        };
+
+        Ok(DeclaredPayload {
+            len: self.payload_len as usize,
+            capacity,
+        })
+    }
+}
+
+pub(crate) fn write_mode_header(
+    image: DynamicImage,
+    header: &ModeHeader,
+) -> Result<DynamicImage, SecretError> {
+    let (width, height) = image.dimensions();
+    let placement = header_placement(width, height, image.color(), header.channels, header.region);
+
+    let mut bytes = header.to_bytes();
+    if header.parity != 0 {
+        bytes = Encoder::new(MODE_PARITY).encode(&bytes).to_vec();
+    }
+
+    if stream_capacity(&placement) < bytes.len() {
+        return Err(SecretError::InsufficientCapacity {
+            needed: bytes.len(),
+            available: stream_capacity(&placement),
+        });
+    }
+
+    debug!("Writing a mode header of {} bytes", bytes.len());
+
+    Ok(embed_stream(&image, &placement, bytes.into_iter()))
+}
+
+// A header is first read as it is, and otherwise corrected with the parity
+// an ECC header carries, for both of its possible lengths.
+fn parse_header(bytes: &[u8]) -> Option<ModeHeader> {
+    ModeHeader::from_bytes(bytes.iter().copied()).or_else(|| {
+        [false, true].into_iter().find_map(|has_region| {
+            let codeword = bytes.get(..protected_len(has_region, true))?;
+            let corrected = Decoder::new(MODE_PARITY).correct(codeword, None).ok()?;
+
+            ModeHeader::from_bytes(corrected.data().iter().copied())
+                .filter(|header| header.parity != 0 && header.region.is_some() == has_region)
+        })
+    })
+}
+
+// The number of rows the longest header can span in a region of this width,
+// which is longest when it is in a single channel.
+pub(crate) fn mode_header_rows(width: u32) -> u32 {
+    mode_header_samples(true, true).div_ceil(width.max(1) as usize) as u32
+}
+
+// Every channel mask the header may have been written with, red alone first.
+fn header_channels(color: ColorType) -> Vec<Channels> {
+    if is_grayscale(color) {
+        return vec![Channels::RED];
+    }
+
+    (1..=Channels::ALPHA.bits() | Channels::RGB.bits())
+        .filter_map(Channels::from_bits)
+        .filter(|channels| color.has_alpha() || !channels.has_alpha())
+        .collect()
+}
+
+// Looks for a header at the start of `region`, then at the start of the
+// image. Only the rows holding the longest header are converted, so images
+// without one cost little to check.
+pub(crate) fn find_mode_header(image: &DynamicImage, region: Option<Region>) -> Option<ModeHeader> {
+    let (width, height) = image.dimensions();
+    let color = image.color();
+
+    for area in region.into_iter().map(Some).chain([None]) {
+        let Region {
+            x,
+            y,
+            width: area_width,
+            height: area_height,
+        } = area.unwrap_or(Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        if x.saturating_add(area_width) > width || y >= height {
+            continue;
+        }
+
+        let rows = mode_header_rows(area_width)
+            .min(area_height)
+            .min(height - y);
+        let top = image.crop_imm(x, y, area_width, rows);
+
+        for channels in header_channels(color) {
+            let placement = header_placement(area_width, rows, color, channels, None);
+            let bytes: Vec<u8> = extract_stream(&top, placement)
+                .take(protected_len(true, true))
+                .collect();
+
+            let header = parse_header(&bytes).filter(|header| {
+                header.region == area && (is_grayscale(color) || header.channels == channels)
+            });
+            if let Some(header) = header {
+                debug!("Found a mode header: {:?}", header);
+                return Some(header);
+            }
+        }
+    }
+
+    None
+}
+
+// Finds a header written without a region. Headers inside a region are only
+// found when the region is given, as extraction does with its options.
+pub fn read_mode_header(image: &DynamicImage) -> Option<ModeHeader> {
+    find_mode_header(image, None)
+}
+
+// Extracts the payload with the options from its mode header.
+pub(crate) fn extract_with_mode(
+    image: &DynamicImage,
+    header: &ModeHeader,
+) -> Result<Vec<u8>, SecretError> {
+    if header.compressed || header.encrypted {
+        return Err(SecretError::UnsupportedMode);
+    }
+
+    let options = header.options();
+    let payload = if header.parity == 0 {
+        extract_lsb_bytes(image, &options)?
+    } else {
+        extract_ecc_bytes(image, &options)?
+    };
+
+    if payload.len() != header.payload_len as usize {
+        return Err(SecretError::LengthMismatch {
+            expected: header.payload_len as usize,
+            actual: payload.len(),
+        });
+    }
+
+    Ok(payload)
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// How far the embed and extract loops and the per-pixel image transforms have
+// come, in bytes or pixels. Each loop adds what it is about to process to the
+// total when it starts, so the total grows as an operation goes from one step
+// to the next. The counters are shared by the whole process, so that a
+// progress bar can poll them from another thread.
+static DONE: AtomicU64 = AtomicU64::new(0);
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: u64,
+    pub total: u64,
+}
+
+pub fn progress() -> Progress {
+    Progress {
+        done: DONE.load(Ordering::Relaxed),
+        total: TOTAL.load(Ordering::Relaxed),
+    }
+}
+
+pub fn reset_progress() {
+    DONE.store(0, Ordering::Relaxed);
+    TOTAL.store(0, Ordering::Relaxed);
+}
+
+pub(crate) fn expect(amount: u64) {
+    TOTAL.fetch_add(amount, Ordering::Relaxed);
+}
+
+pub(crate) fn advance(amount: u64) {
+    DONE.fetch_add(amount, Ordering::Relaxed);
+}
+
+// Counts a row of pixels once its last one is done, which costs less than
+// counting every pixel.
+pub(crate) fn advance_row(x: u32, width: u32) {
+    if x + 1 == width {
+        advance(width as u64);
+    }
+}
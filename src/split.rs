@@ -0,0 +1,166 @@
+use image::DynamicImage;
+
+use crate::{
+    extract_bytes_from_image, hide_payload_in_image, length_header, payload_capacity,
+    read_mode_header, EmbedOptions, PayloadKind, SecretError,
+};
+
+// Every chunk starts with its index, the total number of chunks and the
+// length of the whole payload, all big-endian.
+const CHUNK_HEADER_LEN: usize = 8;
+
+struct Chunk {
+    index: u16,
+    total: u16,
+    payload_len: u32,
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CHUNK_HEADER_LEN + self.data.len());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(&self.payload_len.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SecretError> {
+        if bytes.len() < CHUNK_HEADER_LEN {
+            return Err(SecretError::InvalidChunk);
+        }
+
+        let (header, data) = bytes.split_at(CHUNK_HEADER_LEN);
+
+        Ok(Self {
+            index: u16::from_be_bytes([header[0], header[1]]),
+            total: u16::from_be_bytes([header[2], header[3]]),
+            payload_len: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            data: data.to_vec(),
+        })
+    }
+}
+
+// Returns the hidden image for each carrier that holds a chunk, and `None`
+// for the carriers too small to hold one and those left over once the
+// payload is placed. Every chunk is marked as such in its mode header.
+pub fn hide_bytes_across_images(
+    carriers: &[DynamicImage],
+    payload: &[u8],
+) -> Result<Vec<Option<DynamicImage>>, SecretError> {
+    let payload_len = length_header(payload)?;
+    let options = EmbedOptions {
+        header: true,
+        ..EmbedOptions::default()
+    };
+    let mut chunk_sizes = vec![None; carriers.len()];
+    let mut chunk_count = 0;
+    let mut remaining = payload.len();
+    let mut available = 0;
+
+    for (carrier, chunk_size) in carriers.iter().zip(&mut chunk_sizes) {
+        if remaining == 0 && chunk_count > 0 {
+            break;
+        }
+
+        let capacity = payload_capacity(carrier, &options)?.saturating_sub(CHUNK_HEADER_LEN);
+        if capacity == 0 {
+            continue;
+        }
+        if chunk_count == u16::MAX as usize {
+            break;
+        }
+
+        let size = capacity.min(remaining);
+        *chunk_size = Some(size);
+        chunk_count += 1;
+        remaining -= size;
+        available += capacity;
+    }
+
+    if remaining > 0 || chunk_count == 0 {
+        return Err(SecretError::InsufficientCapacity {
+            needed: payload.len(),
+            available,
+        });
+    }
+
+    let total = chunk_count as u16;
+    let mut index = 0;
+    let mut offset = 0;
+
+    carriers
+        .iter()
+        .zip(chunk_sizes)
+        .map(|(carrier, chunk_size)| {
+            let Some(chunk_size) = chunk_size else {
+                return Ok(None);
+            };
+
+            let chunk = Chunk {
+                index,
+                total,
+                payload_len,
+                data: payload[offset..offset + chunk_size].to_vec(),
+            };
+            index += 1;
+            offset += chunk_size;
+
+            hide_payload_in_image(carrier, &chunk.to_bytes(), &options, PayloadKind::Chunk)
+                .map(|(hidden_image, _)| Some(hidden_image))
+        })
+        .collect()
+}
+
+// Only carriers whose mode header marks a chunk are read, so carriers holding
+// no payload or some other one may be passed along in any order.
+pub fn join_bytes_from_images(carriers: &[DynamicImage]) -> Result<Vec<u8>, SecretError> {
+    let mut chunks = carriers
+        .iter()
+        .filter(|carrier| {
+            read_mode_header(carrier).is_some_and(|header| header.kind == PayloadKind::Chunk)
+        })
+        .map(|carrier| {
+            let bytes = extract_bytes_from_image(carrier, &EmbedOptions::default())?;
+            Chunk::from_bytes(&bytes)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    chunks.sort_by_key(|chunk| chunk.index);
+
+    let Some(first) = chunks.first() else {
+        return Err(SecretError::MissingChunk(0));
+    };
+    let (total, payload_len) = (first.total, first.payload_len);
+
+    if chunks
+        .iter()
+        .any(|chunk| chunk.total != total || chunk.payload_len != payload_len)
+    {
+        return Err(SecretError::InvalidChunk);
+    }
+
+    for index in 0..total {
+        match chunks.get(index as usize) {
+            Some(chunk) if chunk.index == index => {}
+            Some(chunk) if chunk.index < index => return Err(SecretError::InvalidChunk),
+            _ => return Err(SecretError::MissingChunk(index)),
+        }
+    }
+
+    if chunks.len() > total as usize {
+        return Err(SecretError::InvalidChunk);
+    }
+
+    let payload: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.data).collect();
+
+    if payload.len() != payload_len as usize {
+        return Err(SecretError::LengthMismatch {
+            expected: payload_len as usize,
+            actual: payload.len(),
+        });
+    }
+
+    Ok(payload)
+}
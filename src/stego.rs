@@ -0,0 +1,104 @@
+use image::DynamicImage;
+
+use crate::{
+    ecc_payload_capacity, extract_bytes_from_image, extract_bytes_with_ecc,
+    hide_bytes_in_image_with_report, hide_bytes_with_ecc_report, payload_capacity, Channels,
+    EmbedOptions, EmbedReport, Region, SecretError, DEFAULT_PARITY,
+};
+
+pub trait Steganographer {
+    fn embed(&self, carrier: &DynamicImage, payload: &[u8]) -> Result<DynamicImage, SecretError> {
+        self.embed_with_report(carrier, payload)
+            .map(|(hidden_image, _)| hidden_image)
+    }
+
+    fn embed_with_report(
+        &self,
+        carrier: &DynamicImage,
+        payload: &[u8],
+    ) -> Result<(DynamicImage, EmbedReport), SecretError>;
+
+    fn extract(&self, stego: &DynamicImage) -> Result<Vec<u8>, SecretError>;
+
+    // The largest payload, in bytes, that `embed` accepts for this carrier.
+    fn capacity(&self, carrier: &DynamicImage) -> Result<usize, SecretError>;
+}
+
+// `bits` defaults to one bit per 8-bit sample and two bits per 16-bit sample.
+// `lsb_matching` only changes how samples are embedded, extraction reads the
+// low bits either way. With `header`, a mode header is written so that the
+// payload can be extracted without knowing the other options.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LsbStego {
+    pub bits: Option<u8>,
+    pub channels: Channels,
+    pub region: Option<Region>,
+    pub lsb_matching: bool,
+    pub fill_fraction: Option<f64>,
+    pub header: bool,
+}
+
+impl LsbStego {
+    fn options(&self) -> EmbedOptions {
+        EmbedOptions {
+            region: self.region,
+            channels: self.channels,
+            bits: self.bits,
+            lsb_matching: self.lsb_matching,
+            fill_fraction: self.fill_fraction,
+            header: self.header,
+        }
+    }
+}
+
+impl Steganographer for LsbStego {
+    fn embed_with_report(
+        &self,
+        carrier: &DynamicImage,
+        payload: &[u8],
+    ) -> Result<(DynamicImage, EmbedReport), SecretError> {
+        hide_bytes_in_image_with_report(carrier, payload, &self.options())
+    }
+
+    fn extract(&self, stego: &DynamicImage) -> Result<Vec<u8>, SecretError> {
+        extract_bytes_from_image(stego, &self.options())
+    }
+
+    fn capacity(&self, carrier: &DynamicImage) -> Result<usize, SecretError> {
+        payload_capacity(carrier, &self.options())
+    }
+}
+
+// The parity is only used when embedding, extraction reads it from the payload.
+#[derive(Clone, Copy, Debug)]
+pub struct EccStego {
+    pub parity: u8,
+    pub lsb: LsbStego,
+}
+
+impl Default for EccStego {
+    fn default() -> Self {
+        Self {
+            parity: DEFAULT_PARITY,
+            lsb: LsbStego::default(),
+        }
+    }
+}
+
+impl Steganographer for EccStego {
+    fn embed_with_report(
+        &self,
+        carrier: &DynamicImage,
+        payload: &[u8],
+    ) -> Result<(DynamicImage, EmbedReport), SecretError> {
+        hide_bytes_with_ecc_report(carrier, payload, self.parity, &self.lsb.options())
+    }
+
+    fn extract(&self, stego: &DynamicImage) -> Result<Vec<u8>, SecretError> {
+        extract_bytes_with_ecc(stego, &self.lsb.options())
+    }
+
+    fn capacity(&self, carrier: &DynamicImage) -> Result<usize, SecretError> {
+        ecc_payload_capacity(carrier, self.parity, &self.lsb.options())
+    }
+}
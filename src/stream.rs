@@ -0,0 +1,182 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageFormat};
+use png::{BitDepth, DecodingError, Transformations};
+
+use crate::{
+    check_dimensions, extract_stream,
+    mode::{find_mode_header, mode_header_rows},
+    open_image, payload_room, read_length, stream_capacity, EmbedOptions, Region, SecretError,
+    MAX_LENGTH_LEN,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeclaredPayload {
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl DeclaredPayload {
+    pub fn fits(&self) -> bool {
+        self.len <= self.capacity
+    }
+}
+
+fn png_error(error: DecodingError) -> SecretError {
+    match error {
+        DecodingError::IoError(error) => SecretError::Io(error),
+        _ => SecretError::InvalidPng,
+    }
+}
+
+fn png_color(color: png::ColorType, depth: BitDepth) -> Option<ColorType> {
+    let sixteen_bit = depth == BitDepth::Sixteen;
+
+    match color {
+        png::ColorType::Grayscale if sixteen_bit => Some(ColorType::L16),
+        png::ColorType::Grayscale => Some(ColorType::L8),
+        png::ColorType::GrayscaleAlpha if sixteen_bit => Some(ColorType::La16),
+        png::ColorType::GrayscaleAlpha => Some(ColorType::La8),
+        png::ColorType::Rgb if sixteen_bit => Some(ColorType::Rgb16),
+        png::ColorType::Rgb => Some(ColorType::Rgb8),
+        png::ColorType::Rgba if sixteen_bit => Some(ColorType::Rgba16),
+        png::ColorType::Rgba => Some(ColorType::Rgba8),
+        png::ColorType::Indexed => None,
+    }
+}
+
+// Builds an image out of the first decoded rows, with 16-bit samples stored
+// big-endian as in the PNG data.
+fn partial_image(width: u32, rows: u32, color: ColorType, data: Vec<u8>) -> Option<DynamicImage> {
+    let wide = || {
+        data.chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>()
+    };
+
+    match color {
+        ColorType::L8 => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageLuma8),
+        ColorType::La8 => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageLumaA8),
+        ColorType::Rgb8 => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageRgb8),
+        ColorType::Rgba8 => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageRgba8),
+        ColorType::L16 => ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageLuma16),
+        ColorType::La16 => {
+            ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageLumaA16)
+        }
+        ColorType::Rgb16 => {
+            ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageRgb16)
+        }
+        ColorType::Rgba16 => {
+            ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageRgba16)
+        }
+        _ => None,
+    }
+}
+
+// Decodes the PNG one row at a time and stops as soon as the rows holding the
+// length are read, so memory use is a few rows instead of the whole image.
+// Interlaced PNGs spread those rows over the whole file and are skipped.
+fn read_png_header(
+    reader: impl Read,
+    options: &EmbedOptions,
+    max_pixels: u64,
+) -> Result<Option<DeclaredPayload>, SecretError> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+
+    let info = reader.info();
+    let (width, height, interlaced) = (info.width, info.height, info.interlaced);
+    let (png_color_type, depth) = reader.output_color_type();
+    check_dimensions(width, height, max_pixels)?;
+
+    let Some(color) = png_color(png_color_type, depth).filter(|_| !interlaced) else {
+        return Ok(None);
+    };
+
+    let placement = options.placement_for(width, height, color)?;
+    let capacity = stream_capacity(&placement);
+    let region = placement.region;
+
+    let bits_per_row =
+        region.width as u64 * placement.samples_per_pixel() as u64 * placement.bits as u64;
+    let header_bits = MAX_LENGTH_LEN as u64 * 8 + placement.skip as u64 * placement.bits as u64;
+    let header_rows = header_bits
+        .div_ceil(bits_per_row.max(1))
+        .min(region.height as u64) as u32;
+    let region_mode_rows = options
+        .region
+        .map_or(0, |region| region.y + mode_header_rows(region.width));
+    let mode_rows = mode_header_rows(width).max(region_mode_rows).min(height);
+    let rows = (region.y + header_rows).max(mode_rows);
+
+    let mut data = Vec::new();
+    for _ in 0..rows {
+        match reader.next_row().map_err(png_error)? {
+            Some(row) => data.extend_from_slice(row.data()),
+            None => break,
+        }
+    }
+
+    let partial = partial_image(width, rows, color, data).ok_or(SecretError::InvalidPng)?;
+
+    if let Some(header) = find_mode_header(&partial, options.region) {
+        return header.declared(width, height, color).map(Some);
+    }
+    let mut partial_placement = placement;
+    partial_placement.region = Region {
+        height: header_rows,
+        ..region
+    };
+
+    let len = read_length(&mut extract_stream(&partial, partial_placement), capacity)?;
+
+    Ok(Some(DeclaredPayload {
+        len,
+        capacity: payload_room(capacity),
+    }))
+}
+
+// Reads the declared payload length of an image file without extracting the
+// payload. PNG files are streamed, other formats are decoded in full.
+pub fn read_declared_payload(
+    path: &Path,
+    options: &EmbedOptions,
+    max_pixels: u64,
+) -> Result<DeclaredPayload, SecretError> {
+    if ImageFormat::from_path(path).is_ok_and(|format| format == ImageFormat::Png) {
+        let reader = BufReader::new(File::open(path)?);
+
+        if let Some(declared) = read_png_header(reader, options, max_pixels)? {
+            return Ok(declared);
+        }
+    }
+
+    declared_payload(&open_image(path, max_pixels)?, options)
+}
+
+// Reads the declared payload length of an image that is already decoded. The
+// length in a mode header wins over the options.
+pub fn declared_payload(
+    image: &DynamicImage,
+    options: &EmbedOptions,
+) -> Result<DeclaredPayload, SecretError> {
+    if let Some(header) = find_mode_header(image, options.region) {
+        let (width, height) = image.dimensions();
+        return header.declared(width, height, image.color());
+    }
+
+    let placement = options.placement(image)?;
+    let capacity = stream_capacity(&placement);
+
+    let len = read_length(&mut extract_stream(image, placement), capacity)?;
+
+    Ok(DeclaredPayload {
+        len,
+        capacity: payload_room(capacity),
+    })
+}
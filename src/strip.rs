@@ -0,0 +1,38 @@
+use image::DynamicImage;
+
+use crate::{LowBits, Xorshift};
+
+fn strip_samples<S: LowBits>(samples: &mut [S], bits: u8, noise: &mut Xorshift) {
+    for sample in samples {
+        *sample = sample.with_low_bits(bits, noise.next_u8());
+    }
+}
+
+// Replaces the low bits of every sample, alpha included, with noise, so that
+// whatever was hidden in them is lost while each sample moves by less than
+// 2^bits. Zeroing them instead would leave a visible pattern in the bit
+// planes. At most 8 bits are replaced, and the color type is kept, except for
+// floating point images, which become 16-bit.
+pub fn strip_lsb(image: &DynamicImage, bits: u8) -> DynamicImage {
+    let bits = bits.min(8);
+    let mut noise = Xorshift::new();
+    let mut stripped_image = image.clone();
+
+    match &mut stripped_image {
+        DynamicImage::ImageLuma8(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageLumaA8(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageRgb8(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageRgba8(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageLuma16(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageLumaA16(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageRgb16(buffer) => strip_samples(buffer, bits, &mut noise),
+        DynamicImage::ImageRgba16(buffer) => strip_samples(buffer, bits, &mut noise),
+        _ => {
+            let mut buffer = image.to_rgba16();
+            strip_samples(&mut buffer, bits, &mut noise);
+            stripped_image = DynamicImage::ImageRgba16(buffer);
+        }
+    }
+
+    stripped_image
+}
@@ -0,0 +1,172 @@
+use image::DynamicImage;
+use log::warn;
+
+use crate::{EmbedReport, SecretError, Steganographer};
+
+// Tagged payloads are stored as one regular payload: a magic number, the
+// number of records, a table of (tag, offset, length) records and then the
+// payloads one after the other. Offsets count from the end of the table, tags
+// take one length byte and the other fields are big-endian u32.
+const TAGS_MAGIC: &[u8; 4] = b"STAG";
+const MAX_TAG_LEN: usize = u8::MAX as usize;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaggedPayloads {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl TaggedPayloads {
+    pub fn get(&self, tag: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(entry_tag, _)| entry_tag == tag)
+            .map(|(_, payload)| payload.as_slice())
+    }
+
+    // Replaces the payload of a tag that already exists, and otherwise adds
+    // it after the others.
+    pub fn insert(&mut self, tag: &str, payload: &[u8]) -> Result<(), SecretError> {
+        if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+            return Err(SecretError::InvalidTag(tag.to_string()));
+        }
+
+        match self
+            .entries
+            .iter_mut()
+            .find(|(entry_tag, _)| entry_tag == tag)
+        {
+            Some((_, entry_payload)) => *entry_payload = payload.to_vec(),
+            None => self.entries.push((tag.to_string(), payload.to_vec())),
+        }
+
+        Ok(())
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(tag, _)| tag.as_str())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SecretError> {
+        let mut table = TAGS_MAGIC.to_vec();
+        table.extend_from_slice(&u32_field(self.entries.len())?.to_be_bytes());
+
+        let mut data = Vec::new();
+        for (tag, payload) in &self.entries {
+            table.push(tag.len() as u8);
+            table.extend_from_slice(tag.as_bytes());
+            table.extend_from_slice(&u32_field(data.len())?.to_be_bytes());
+            table.extend_from_slice(&u32_field(payload.len())?.to_be_bytes());
+            data.extend_from_slice(payload);
+        }
+
+        u32_field(data.len())?;
+        table.extend_from_slice(&data);
+        Ok(table)
+    }
+
+    // Returns `None` for payloads that do not start with the magic number, and
+    // an error for tables that do but point outside the payloads.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Result<Self, SecretError>> {
+        let mut reader = bytes.strip_prefix(TAGS_MAGIC)?;
+        Some(read_table(&mut reader))
+    }
+}
+
+fn u32_field(len: usize) -> Result<u32, SecretError> {
+    u32::try_from(len).map_err(|_| SecretError::PayloadTooLarge(len))
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], SecretError> {
+    if reader.len() < len {
+        return Err(SecretError::InvalidTagTable);
+    }
+
+    let (taken, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(taken)
+}
+
+fn take_u32(reader: &mut &[u8]) -> Result<usize, SecretError> {
+    let bytes = take(reader, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+fn read_table(reader: &mut &[u8]) -> Result<TaggedPayloads, SecretError> {
+    let count = take_u32(reader)?;
+
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let tag_len = take(reader, 1)?[0] as usize;
+        let tag = String::from_utf8(take(reader, tag_len)?.to_vec())
+            .map_err(|_| SecretError::InvalidTagTable)?;
+        records.push((tag, take_u32(reader)?, take_u32(reader)?));
+    }
+
+    let data = *reader;
+    let entries = records
+        .into_iter()
+        .map(|(tag, offset, len)| {
+            let payload = offset
+                .checked_add(len)
+                .and_then(|end| data.get(offset..end))
+                .ok_or(SecretError::InvalidTagTable)?;
+            Ok((tag, payload.to_vec()))
+        })
+        .collect::<Result<_, SecretError>>()?;
+
+    Ok(TaggedPayloads { entries })
+}
+
+pub fn read_tagged_payloads(
+    stego: &dyn Steganographer,
+    image: &DynamicImage,
+) -> Result<TaggedPayloads, SecretError> {
+    let bytes = stego.extract(image)?;
+    TaggedPayloads::from_bytes(&bytes).unwrap_or(Ok(TaggedPayloads::default()))
+}
+
+// The tagged payloads of the carrier with this one added, ready to be
+// embedded. A new table is started when the carrier holds none, and an
+// untagged payload in it is lost.
+pub fn append_tagged_payload(
+    stego: &dyn Steganographer,
+    carrier: &DynamicImage,
+    tag: &str,
+    payload: &[u8],
+) -> Result<Vec<u8>, SecretError> {
+    let mut tagged = match stego.extract(carrier) {
+        Ok(bytes) => TaggedPayloads::from_bytes(&bytes).unwrap_or_else(|| {
+            if !bytes.is_empty() {
+                warn!("The carrier holds an untagged payload, which will be overwritten");
+            }
+            Ok(TaggedPayloads::default())
+        })?,
+        Err(_) => TaggedPayloads::default(),
+    };
+    tagged.insert(tag, payload)?;
+
+    tagged.to_bytes()
+}
+
+pub fn hide_tagged_payload(
+    stego: &dyn Steganographer,
+    carrier: &DynamicImage,
+    tag: &str,
+    payload: &[u8],
+) -> Result<(DynamicImage, EmbedReport), SecretError> {
+    stego.embed_with_report(
+        carrier,
+        &append_tagged_payload(stego, carrier, tag, payload)?,
+    )
+}
+
+pub fn extract_tagged_payload(
+    stego: &dyn Steganographer,
+    image: &DynamicImage,
+    tag: &str,
+) -> Result<Vec<u8>, SecretError> {
+    read_tagged_payloads(stego, image)?
+        .get(tag)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| SecretError::TagNotFound(tag.to_string()))
+}
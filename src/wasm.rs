@@ -0,0 +1,30 @@
+use image::ImageFormat;
+use wasm_bindgen::prelude::*;
+
+use crate::{extract_payload_bytes, extract_text_bytes, hide_payload_bytes, hide_text_bytes};
+
+/// Hides `text` in the encoded `carrier` image and returns the stego image as
+/// PNG bytes.
+#[wasm_bindgen(js_name = hideText)]
+pub fn hide_text(carrier: &[u8], text: &str) -> Result<Vec<u8>, JsError> {
+    Ok(hide_text_bytes(carrier, text, ImageFormat::Png)?)
+}
+
+/// Returns the text hidden in the encoded `stego` image.
+#[wasm_bindgen(js_name = extractText)]
+pub fn extract_text(stego: &[u8]) -> Result<String, JsError> {
+    Ok(extract_text_bytes(stego)?)
+}
+
+/// Hides the `payload` bytes in the encoded `carrier` image and returns the
+/// stego image as PNG bytes.
+#[wasm_bindgen(js_name = hidePayload)]
+pub fn hide_payload(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(hide_payload_bytes(carrier, payload, ImageFormat::Png)?)
+}
+
+/// Returns the payload bytes hidden in the encoded `stego` image.
+#[wasm_bindgen(js_name = extractPayload)]
+pub fn extract_payload(stego: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(extract_payload_bytes(stego)?)
+}
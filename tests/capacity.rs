@@ -0,0 +1,252 @@
+use image::{DynamicImage, ImageBuffer, RgbImage};
+use proptest::prelude::*;
+use secret::{
+    declared_payload, ecc_payload_capacity, extract_bytes_from_image, extract_bytes_with_ecc,
+    extract_text_from_image_with_report, hide_bytes_in_image, hide_bytes_in_image_with_report,
+    hide_bytes_with_ecc, hide_image, hide_text_in_image_with_report, load_image, payload_capacity,
+    EmbedOptions, EmbedReport, ExtractReport, HideImageOptions, SecretError, DEFAULT_MAX_PIXELS,
+};
+
+// One pixel wide carriers move to the next row after every pixel, so the
+// length header and the payload always span several rows.
+fn narrow_carrier() -> impl Strategy<Value = DynamicImage> {
+    let rgb8 = (1..=512u32).prop_flat_map(|height| {
+        prop::collection::vec(any::<u8>(), height as usize * 3).prop_map(move |data| {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(1, height, data).unwrap())
+        })
+    });
+    let rgb16 = (1..=256u32).prop_flat_map(|height| {
+        prop::collection::vec(any::<u16>(), height as usize * 3).prop_map(move |data| {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(1, height, data).unwrap())
+        })
+    });
+
+    prop_oneof![rgb8, rgb16]
+}
+
+fn embed_options() -> impl Strategy<Value = EmbedOptions> {
+    prop::option::of(1..=8u8).prop_map(|bits| EmbedOptions {
+        bits,
+        ..EmbedOptions::default()
+    })
+}
+
+proptest! {
+    #[test]
+    fn payloads_fill_narrow_carriers_exactly(
+        image in narrow_carrier(),
+        options in embed_options(),
+        byte in any::<u8>(),
+    ) {
+        let capacity = payload_capacity(&image, &options).unwrap();
+        let payload = vec![byte; capacity];
+
+        match hide_bytes_in_image(&image, &payload, &options) {
+            Ok(hidden_image) => {
+                prop_assert_eq!(extract_bytes_from_image(&hidden_image, &options).unwrap(), payload);
+            }
+            // Only carriers without room for the length header reject an
+            // empty payload.
+            Err(SecretError::InsufficientCapacity { .. }) => prop_assert_eq!(capacity, 0),
+            Err(error) => prop_assert!(false, "unexpected error: {}", error),
+        }
+
+        let too_long = vec![byte; capacity + 1];
+        let rejected = matches!(
+            hide_bytes_in_image(&image, &too_long, &options),
+            Err(SecretError::InsufficientCapacity { needed, available })
+                if capacity == 0 || (needed == capacity + 1 && available == capacity)
+        );
+        prop_assert!(rejected);
+    }
+
+    #[test]
+    fn ecc_payloads_fill_narrow_carriers_exactly(
+        image in narrow_carrier(),
+        options in embed_options(),
+        parity in 2..=32u8,
+    ) {
+        let capacity = ecc_payload_capacity(&image, parity, &options).unwrap();
+        let payload = vec![0x5a; capacity];
+
+        match hide_bytes_with_ecc(&image, &payload, parity, &options) {
+            Ok(hidden_image) => {
+                prop_assert_eq!(extract_bytes_with_ecc(&hidden_image, &options).unwrap(), payload);
+            }
+            Err(SecretError::InsufficientCapacity { .. }) => prop_assert_eq!(capacity, 0),
+            Err(error) => prop_assert!(false, "unexpected error: {}", error),
+        }
+
+        let too_long = vec![0x5a; capacity + 1];
+        let rejected = matches!(
+            hide_bytes_with_ecc(&image, &too_long, parity, &options),
+            Err(SecretError::InsufficientCapacity { .. })
+        );
+        prop_assert!(rejected);
+    }
+}
+
+#[test]
+fn reports_count_the_bytes_and_pixels_used() {
+    let image = DynamicImage::ImageRgb8(RgbImage::new(100, 10));
+    let text = "twenty-five bytes of text";
+
+    let (hidden_image, report) = hide_text_in_image_with_report(&image, text).unwrap();
+
+    // The red low bits of 1000 pixels hold 125 bytes, one of which is the
+    // length, and the 26 bytes written span 208 pixels.
+    assert_eq!(
+        report,
+        EmbedReport {
+            bytes_written: 25,
+            pixels_used: 208,
+            capacity: 124,
+        }
+    );
+    assert_eq!(
+        extract_text_from_image_with_report(&hidden_image).unwrap(),
+        (text.to_string(), ExtractReport { bytes_read: 25 })
+    );
+}
+
+#[test]
+fn capacity_errors_keep_their_sizes_in_json() {
+    let image = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+    let error = hide_bytes_in_image(&image, &[0; 16], &EmbedOptions::default()).unwrap_err();
+
+    assert_eq!(
+        error.to_json(),
+        serde_json::json!({
+            "kind": "InsufficientCapacity",
+            "needed": 16,
+            "available": 7,
+            "message": "insufficient space in the image: 16 bytes needed, 7 available",
+        })
+    );
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(&chunk);
+    png.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+}
+
+// A carrier whose red low bits hold exactly `stream_len` bytes.
+fn carrier_of_stream_len(stream_len: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::new(8, stream_len))
+}
+
+#[test]
+fn length_headers_grow_at_varint_boundaries() {
+    // The largest payload and the length of its header, for streams on
+    // either side of the one and two byte boundaries.
+    for (stream_len, capacity, length_len) in [
+        (128, 127, 1),
+        (129, 127, 1),
+        (130, 128, 2),
+        (16385, 16383, 2),
+        (16387, 16384, 3),
+    ] {
+        let image = carrier_of_stream_len(stream_len);
+        let options = EmbedOptions::default();
+        assert_eq!(payload_capacity(&image, &options).unwrap(), capacity);
+
+        let payload = vec![0xa5; capacity];
+        let (hidden_image, report) =
+            hide_bytes_in_image_with_report(&image, &payload, &options).unwrap();
+        assert_eq!(report.pixels_used, (capacity + length_len) as u64 * 8);
+        assert_eq!(
+            extract_bytes_from_image(&hidden_image, &options).unwrap(),
+            payload
+        );
+
+        let declared = declared_payload(&hidden_image, &options).unwrap();
+        assert_eq!((declared.len, declared.capacity), (capacity, capacity));
+
+        assert!(matches!(
+            hide_bytes_in_image(&image, &vec![0xa5; capacity + 1], &options),
+            Err(SecretError::InsufficientCapacity { needed, available })
+                if needed == capacity + 1 && available == capacity
+        ));
+    }
+}
+
+#[test]
+fn five_byte_lengths_are_read_in_full() {
+    // 2^28, the smallest length taking five bytes, written by hand since a
+    // payload that long would not fit in a test.
+    let length = [0x80, 0x80, 0x80, 0x80, 0x01];
+    let bits = length
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+
+    let mut buffer = RgbImage::new(8, 64);
+    for (pixel, bit) in buffer.pixels_mut().zip(bits) {
+        pixel[0] = bit;
+    }
+
+    let declared =
+        declared_payload(&DynamicImage::ImageRgb8(buffer), &EmbedOptions::default()).unwrap();
+    assert_eq!(declared.len, 1 << 28);
+    assert_eq!(declared.capacity, 63);
+    assert!(!declared.fits());
+}
+
+// An image that never had anything hidden in it: its low bits read as a
+// length far beyond what the image holds.
+#[test]
+fn images_without_a_payload_declare_too_long_a_length() {
+    let plain_image = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, image::Rgb([255; 3])));
+    let options = EmbedOptions::default();
+
+    let declared = declared_payload(&plain_image, &options).unwrap();
+    assert!(!declared.fits());
+    assert!(matches!(
+        extract_bytes_from_image(&plain_image, &options),
+        Err(SecretError::DeclaredLengthExceedsCapacity { .. })
+    ));
+}
+
+// A PNG whose header claims 100000x100000 pixels, with no pixel data. The
+// decoder reads up to the first data chunk before reporting the dimensions.
+fn huge_png_header() -> Vec<u8> {
+    let mut ihdr = 100_000u32.to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&100_000u32.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &ihdr);
+    png_chunk(&mut png, b"IDAT", &[]);
+    png
+}
+
+#[test]
+fn images_over_the_pixel_limit_are_refused() {
+    assert!(matches!(
+        load_image(&huge_png_header(), DEFAULT_MAX_PIXELS),
+        Err(SecretError::DimensionsTooLarge {
+            width: 100_000,
+            height: 100_000,
+            ..
+        })
+    ));
+
+    let image = DynamicImage::ImageRgb8(RgbImage::new(40, 30));
+    let options = HideImageOptions {
+        expand: true,
+        max_pixels: 1000,
+        ..HideImageOptions::default()
+    };
+    let secret = DynamicImage::ImageRgb8(RgbImage::new(40, 40));
+
+    assert!(matches!(
+        hide_image(&image, &secret, &options),
+        Err(SecretError::DimensionsTooLarge {
+            max_pixels: 1000,
+            ..
+        })
+    ));
+}
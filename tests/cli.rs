@@ -0,0 +1,463 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+use image::{DynamicImage, RgbImage};
+use secret::{hide_bytes_in_image, EmbedOptions};
+use serde_json::Value;
+
+fn secret(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_secret"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// A directory of its own for every test, since tests run in parallel.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("secret-cli-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn carrier() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+        image::Rgb([(x * 4) as u8, (y * 5) as u8, (x ^ y) as u8])
+    }))
+}
+
+fn save_carrier(path: &Path) {
+    carrier().save(path).unwrap();
+}
+
+fn json_error(output: &Output) -> Value {
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    json["error"].clone()
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn images_over_the_pixel_limit_fail_with_an_error() {
+    let dir = temp_dir("pixel-limit");
+    let carrier = dir.join("carrier.png");
+    save_carrier(&carrier);
+
+    let output = secret(&[
+        "--json",
+        "--max-pixels",
+        "10",
+        "decrypt_img",
+        "--source",
+        path_str(&carrier),
+        "--output",
+        path_str(&dir.join("out.png")),
+    ]);
+
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "DimensionsTooLarge");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+}
+
+#[test]
+fn missing_inputs_fail_with_a_json_error() {
+    let dir = temp_dir("missing-input");
+
+    let output = secret(&[
+        "--json",
+        "hide_txt",
+        "--image",
+        path_str(&dir.join("nope.png")),
+        "--text",
+        "hello",
+        "--output",
+        path_str(&dir.join("out.png")),
+    ]);
+
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "Io");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+}
+
+#[test]
+fn directories_of_jpegs_are_hidden_into_pngs() {
+    let dir = temp_dir("directory");
+    let (input_dir, output_dir) = (dir.join("in"), dir.join("out"));
+    fs::create_dir_all(&input_dir).unwrap();
+    save_carrier(&input_dir.join("photo.jpg"));
+
+    let output = secret(&[
+        "hide_txt",
+        "--image",
+        path_str(&input_dir),
+        "--text",
+        "watermark",
+        "--output",
+        path_str(&output_dir),
+    ]);
+    assert!(output.status.success());
+    assert!(!output_dir.join("photo.jpg").exists());
+
+    let output = secret(&[
+        "decrypt_txt",
+        "--image",
+        path_str(&output_dir.join("photo.png")),
+        "--output",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"watermark");
+}
+
+#[test]
+fn extracted_text_is_written_as_it_was_hidden() {
+    let dir = temp_dir("raw-output");
+    let (carrier, hidden, text) = (
+        dir.join("carrier.png"),
+        dir.join("hidden.png"),
+        dir.join("text"),
+    );
+    save_carrier(&carrier);
+
+    let output = secret(&[
+        "hide_txt",
+        "--image",
+        path_str(&carrier),
+        "--text",
+        "héllo wörld",
+        "--output",
+        path_str(&hidden),
+    ]);
+    assert!(output.status.success());
+
+    let output = secret(&[
+        "decrypt_txt",
+        "--image",
+        path_str(&hidden),
+        "--output",
+        path_str(&text),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&text).unwrap(), "héllo wörld".as_bytes());
+}
+
+#[test]
+fn payloads_that_are_not_utf8_are_only_written_raw() {
+    let dir = temp_dir("invalid-text");
+    let (hidden, raw) = (dir.join("hidden.png"), dir.join("raw"));
+    hide_bytes_in_image(&carrier(), &[0xff, 0xfe, b'a'], &EmbedOptions::default())
+        .unwrap()
+        .save(&hidden)
+        .unwrap();
+
+    let output = secret(&["--json", "decrypt_txt", "--image", path_str(&hidden)]);
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "InvalidText");
+
+    let output = secret(&[
+        "decrypt_txt",
+        "--image",
+        path_str(&hidden),
+        "--output",
+        path_str(&raw),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&raw).unwrap(), [0xff, 0xfe, b'a']);
+}
+
+#[test]
+fn dry_runs_are_refused_by_commands_that_cannot_honour_them() {
+    let dir = temp_dir("dry-run");
+    let (carrier, stripped) = (dir.join("carrier.png"), dir.join("stripped.png"));
+    save_carrier(&carrier);
+
+    let output = secret(&[
+        "strip",
+        "--dry-run",
+        "--image",
+        path_str(&carrier),
+        "--output",
+        path_str(&stripped),
+    ]);
+    assert!(!output.status.success());
+    assert!(!stripped.exists());
+
+    let output = secret(&[
+        "hide_txt",
+        "--dry-run",
+        "--image",
+        path_str(&carrier),
+        "--text",
+        "hello",
+        "--output",
+        path_str(&stripped),
+    ]);
+    assert!(output.status.success());
+    assert!(!stripped.exists());
+}
+
+#[test]
+fn images_without_a_payload_fail_with_a_json_error() {
+    let dir = temp_dir("no-payload");
+    let plain = dir.join("plain.png");
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 48, image::Rgb([255; 3])))
+        .save(&plain)
+        .unwrap();
+
+    let output = secret(&["--json", "decrypt_txt", "--image", path_str(&plain)]);
+
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "DeclaredLengthExceedsCapacity");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+}
+
+#[test]
+fn existing_outputs_are_only_overwritten_with_force() {
+    let dir = temp_dir("force");
+    let (carrier, hidden) = (dir.join("carrier.png"), dir.join("hidden.png"));
+    save_carrier(&carrier);
+    fs::write(&hidden, b"keep me").unwrap();
+
+    let hide_args = [
+        "--json",
+        "hide_txt",
+        "--image",
+        path_str(&carrier),
+        "--text",
+        "hello",
+        "--output",
+        path_str(&hidden),
+    ];
+
+    let output = secret(&hide_args);
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "OutputExists");
+    assert_eq!(fs::read(&hidden).unwrap(), b"keep me");
+
+    let output = secret(&[&hide_args[..], &["--force"]].concat());
+    assert!(output.status.success());
+
+    let output = secret(&["decrypt_txt", "--image", path_str(&hidden), "--output", "-"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello");
+}
+
+#[test]
+fn hiding_the_same_text_twice_gives_the_same_bytes() {
+    let dir = temp_dir("deterministic");
+    let carrier = dir.join("carrier.png");
+    save_carrier(&carrier);
+
+    for compression in ["fast", "default", "best"] {
+        let hidden: Vec<PathBuf> = ["first", "second"]
+            .iter()
+            .map(|name| {
+                let hidden = dir.join(format!("{}-{}.png", compression, name));
+                let output = secret(&[
+                    "hide_txt",
+                    "--image",
+                    path_str(&carrier),
+                    "--text",
+                    "hello",
+                    "--png-compression",
+                    compression,
+                    "--output",
+                    path_str(&hidden),
+                ]);
+                assert!(output.status.success());
+                hidden
+            })
+            .collect();
+
+        assert_eq!(fs::read(&hidden[0]).unwrap(), fs::read(&hidden[1]).unwrap());
+    }
+}
+
+#[test]
+fn texts_round_trip_through_bmp_and_webp_outputs() {
+    let dir = temp_dir("lossless-formats");
+    let carrier = dir.join("carrier.png");
+    save_carrier(&carrier);
+
+    for name in ["hidden.bmp", "hidden.webp"] {
+        let hidden = dir.join(name);
+        let output = secret(&[
+            "hide_txt",
+            "--image",
+            path_str(&carrier),
+            "--text",
+            "lossless",
+            "--output",
+            path_str(&hidden),
+        ]);
+        assert!(output.status.success());
+
+        let output = secret(&["decrypt_txt", "--image", path_str(&hidden), "--output", "-"]);
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"lossless");
+    }
+}
+
+#[test]
+fn lossy_webps_are_refused_when_extracting() {
+    let dir = temp_dir("lossy-webp");
+    let lossy = dir.join("lossy.webp");
+    fs::write(&lossy, b"RIFF\x0c\0\0\0WEBPVP8 \0\0\0\0").unwrap();
+
+    let output = secret(&["--json", "decrypt_txt", "--image", path_str(&lossy)]);
+
+    assert!(!output.status.success());
+    assert_eq!(json_error(&output)["kind"], "LossyWebp");
+}
+
+#[test]
+fn verify_succeeds_only_when_the_hidden_text_matches() {
+    let dir = temp_dir("verify");
+    let (carrier, hidden, expected) = (
+        dir.join("carrier.png"),
+        dir.join("hidden.png"),
+        dir.join("expected"),
+    );
+    save_carrier(&carrier);
+    fs::write(&expected, "hello").unwrap();
+
+    let output = secret(&[
+        "hide_txt",
+        "--image",
+        path_str(&carrier),
+        "--text",
+        "hello",
+        "--output",
+        path_str(&hidden),
+    ]);
+    assert!(output.status.success());
+
+    let output = secret(&["verify", "--image", path_str(&hidden), "--expect", "hello"]);
+    assert!(output.status.success());
+    let output = secret(&[
+        "verify",
+        "--image",
+        path_str(&hidden),
+        "--expect-file",
+        path_str(&expected),
+    ]);
+    assert!(output.status.success());
+
+    let output = secret(&[
+        "--json",
+        "verify",
+        "--image",
+        path_str(&hidden),
+        "--expect",
+        "help",
+    ]);
+    assert!(!output.status.success());
+    let error = json_error(&output);
+    assert_eq!(error["kind"], "PayloadMismatch");
+    assert_eq!(error["first_difference"], 3);
+}
+
+#[test]
+fn split_files_are_written_and_joined_past_other_payloads() {
+    let dir = temp_dir("split");
+    let (file, joined, output_dir) = (dir.join("file"), dir.join("joined"), dir.join("out"));
+    let carriers: Vec<PathBuf> = ["a.png", "b.png", "c.png"]
+        .iter()
+        .map(|name| dir.join(name))
+        .collect();
+    for carrier in &carriers[..2] {
+        save_carrier(carrier);
+    }
+    let contents: Vec<u8> = (0..500).map(|byte| (byte * 7) as u8).collect();
+    fs::write(&file, &contents).unwrap();
+
+    // The last carrier already holds a text, and is left over by the split.
+    save_carrier(&dir.join("plain.png"));
+    let output = secret(&[
+        "hide_txt",
+        "--image",
+        path_str(&dir.join("plain.png")),
+        "--text",
+        "hello",
+        "--output",
+        path_str(&carriers[2]),
+    ]);
+    assert!(output.status.success());
+
+    let mut args = vec!["hide_file_split", "--file", path_str(&file), "--carriers"];
+    args.extend(carriers.iter().map(|carrier| path_str(carrier)));
+    args.extend(["--output", path_str(&output_dir)]);
+    assert!(secret(&args).status.success());
+    assert!(output_dir.join("b.png").exists());
+    assert!(!output_dir.join("c.png").exists());
+
+    let output = secret(&[
+        "extract_file_join",
+        "--images",
+        path_str(&carriers[2]),
+        path_str(&output_dir.join("b.png")),
+        path_str(&output_dir.join("a.png")),
+        "--output",
+        path_str(&joined),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(&joined).unwrap(), contents);
+}
+
+// A carrier PNG with a text chunk, to see whether --keep-metadata copied it.
+fn save_carrier_with_text(path: &Path) {
+    let carrier = carrier().to_rgb8();
+    let mut encoder = png::Encoder::new(fs::File::create(path).unwrap(), 64, 48);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder
+        .add_text_chunk("Comment".to_string(), "kept".to_string())
+        .unwrap();
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(carrier.as_raw()).unwrap();
+}
+
+fn has_text_chunk(path: &Path) -> bool {
+    let png = fs::read(path).unwrap();
+    png.windows(16).any(|window| window == b"tEXtComment\0kept")
+}
+
+#[test]
+fn metadata_is_kept_in_directory_and_split_outputs() {
+    let dir = temp_dir("keep-metadata");
+    let (input_dir, output_dir, split_dir) = (dir.join("in"), dir.join("out"), dir.join("split"));
+    fs::create_dir_all(&input_dir).unwrap();
+    save_carrier_with_text(&input_dir.join("a.png"));
+
+    let output = secret(&[
+        "hide_txt",
+        "--image",
+        path_str(&input_dir),
+        "--text",
+        "watermark",
+        "--output",
+        path_str(&output_dir),
+        "--keep-metadata",
+    ]);
+    assert!(output.status.success());
+    assert!(has_text_chunk(&output_dir.join("a.png")));
+
+    let file = dir.join("file");
+    fs::write(&file, b"split contents").unwrap();
+    let output = secret(&[
+        "hide_file_split",
+        "--file",
+        path_str(&file),
+        "--carriers",
+        path_str(&input_dir.join("a.png")),
+        "--output",
+        path_str(&split_dir),
+        "--keep-metadata",
+    ]);
+    assert!(output.status.success());
+    assert!(has_text_chunk(&split_dir.join("a.png")));
+}
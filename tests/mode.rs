@@ -0,0 +1,232 @@
+use image::{DynamicImage, ImageBuffer, RgbImage, RgbaImage};
+use secret::{
+    extract_bytes_from_image, extract_meta_from_image, hide_bytes_in_image, hide_meta_in_image,
+    read_mode_header, Channels, EccStego, EmbedOptions, LsbStego, Metadata, PayloadKind, Region,
+    SecretError, Steganographer,
+};
+
+fn carrier() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(96, 64, |x, y| {
+        image::Rgb([(x * 3) as u8, (y * 4) as u8, (x ^ y) as u8])
+    }))
+}
+
+fn carrier_16() -> DynamicImage {
+    DynamicImage::ImageRgb16(ImageBuffer::from_fn(96, 64, |x, y| {
+        image::Rgb([(x * 700) as u16, (y * 1000) as u16, (x * y) as u16])
+    }))
+}
+
+fn region() -> Region {
+    Region {
+        x: 5,
+        y: 0,
+        width: 60,
+        height: 40,
+    }
+}
+
+fn stegos() -> Vec<Box<dyn Steganographer>> {
+    let region = region();
+    let lsb = LsbStego {
+        header: true,
+        ..LsbStego::default()
+    };
+
+    vec![
+        Box::new(lsb),
+        Box::new(LsbStego {
+            bits: Some(3),
+            channels: Channels::RGB,
+            ..lsb
+        }),
+        Box::new(LsbStego {
+            bits: Some(2),
+            channels: "gb".parse().unwrap(),
+            region: Some(region),
+            ..lsb
+        }),
+        Box::new(LsbStego {
+            lsb_matching: true,
+            channels: Channels::BLUE,
+            ..lsb
+        }),
+        Box::new(EccStego { parity: 8, lsb }),
+        Box::new(EccStego {
+            parity: 32,
+            lsb: LsbStego {
+                bits: Some(4),
+                region: Some(region),
+                ..lsb
+            },
+        }),
+    ]
+}
+
+#[test]
+fn payloads_are_extracted_without_any_options() {
+    let payload = b"hidden with a mode header".to_vec();
+
+    for carrier in [carrier(), carrier_16()] {
+        for stego in stegos() {
+            let hidden_image = stego.embed(&carrier, &payload).unwrap();
+
+            // A header inside a region is only found with the region.
+            let options = match read_mode_header(&hidden_image) {
+                Some(_) => EmbedOptions::default(),
+                None => EmbedOptions {
+                    region: Some(region()),
+                    ..EmbedOptions::default()
+                },
+            };
+            assert_eq!(
+                extract_bytes_from_image(&hidden_image, &options).unwrap(),
+                payload
+            );
+        }
+    }
+}
+
+#[test]
+fn headers_stay_inside_the_region_and_channels() {
+    let carrier = carrier();
+    let region = Region {
+        x: 40,
+        y: 16,
+        width: 32,
+        height: 32,
+    };
+    let stego = LsbStego {
+        channels: Channels::BLUE,
+        region: Some(region),
+        header: true,
+        ..LsbStego::default()
+    };
+
+    let hidden_image = stego.embed(&carrier, b"inside").unwrap();
+
+    for ((x, y, before), after) in carrier
+        .to_rgb8()
+        .enumerate_pixels()
+        .zip(hidden_image.to_rgb8().pixels())
+    {
+        if !region.contains(x, y) {
+            assert_eq!(before, after, "pixel ({x}, {y}) changed");
+        }
+        assert_eq!(before.0[..2], after.0[..2]);
+    }
+
+    let options = EmbedOptions {
+        region: Some(region),
+        ..EmbedOptions::default()
+    };
+    assert_eq!(
+        extract_bytes_from_image(&hidden_image, &options).unwrap(),
+        b"inside"
+    );
+}
+
+#[test]
+fn ecc_headers_survive_flipped_bits() {
+    let stego = EccStego {
+        parity: 16,
+        lsb: LsbStego {
+            header: true,
+            ..LsbStego::default()
+        },
+    };
+    let mut hidden_image = stego.embed(&carrier(), b"noisy channel").unwrap().to_rgb8();
+
+    // The red samples of the first pixels hold the header.
+    for x in [2, 9, 40] {
+        hidden_image.get_pixel_mut(x, 0)[0] ^= 1;
+    }
+    let hidden_image = DynamicImage::ImageRgb8(hidden_image);
+
+    assert_eq!(read_mode_header(&hidden_image).unwrap().parity, 16);
+    assert_eq!(
+        extract_bytes_from_image(&hidden_image, &EmbedOptions::default()).unwrap(),
+        b"noisy channel"
+    );
+}
+
+#[test]
+fn images_without_a_header_use_the_options() {
+    let options = EmbedOptions {
+        bits: Some(2),
+        ..EmbedOptions::default()
+    };
+    let stego = LsbStego {
+        bits: Some(2),
+        ..LsbStego::default()
+    };
+
+    let hidden_image = stego.embed(&carrier(), b"no header").unwrap();
+
+    assert_eq!(read_mode_header(&hidden_image), None);
+    assert_eq!(
+        extract_bytes_from_image(&hidden_image, &options).unwrap(),
+        b"no header"
+    );
+}
+
+#[test]
+fn alpha_payloads_leave_the_colors_untouched() {
+    let carrier = DynamicImage::ImageRgba8(RgbaImage::from_fn(96, 64, |x, y| {
+        image::Rgba([(x * 3) as u8, (y * 4) as u8, (x ^ y) as u8, 200])
+    }));
+    let stego = LsbStego {
+        channels: Channels::ALPHA,
+        header: true,
+        ..LsbStego::default()
+    };
+
+    let hidden_image = stego.embed(&carrier, b"in the alpha channel").unwrap();
+
+    assert_eq!(
+        extract_bytes_from_image(&hidden_image, &EmbedOptions::default()).unwrap(),
+        b"in the alpha channel"
+    );
+    for (before, after) in carrier
+        .to_rgba8()
+        .pixels()
+        .zip(hidden_image.to_rgba8().pixels())
+    {
+        assert_eq!(before.0[..3], after.0[..3]);
+    }
+
+    assert!(matches!(
+        stego.embed(&DynamicImage::ImageRgb8(carrier.to_rgb8()), b"no alpha"),
+        Err(SecretError::NoAlphaChannel)
+    ));
+}
+
+#[test]
+fn metadata_payloads_are_marked_as_such() {
+    let options = EmbedOptions {
+        header: true,
+        ..EmbedOptions::default()
+    };
+    let meta = Metadata::from([(String::from("author"), String::from("me"))]);
+
+    let meta_image = hide_meta_in_image(&carrier(), &meta, &options).unwrap();
+    assert_eq!(
+        read_mode_header(&meta_image).unwrap().kind,
+        PayloadKind::Metadata
+    );
+    assert_eq!(
+        extract_meta_from_image(&meta_image, &options).unwrap(),
+        meta
+    );
+
+    // Plain JSON hidden as bytes is not taken for metadata.
+    let bytes_image = hide_bytes_in_image(&carrier(), br#"{"author":"me"}"#, &options).unwrap();
+    assert_eq!(
+        read_mode_header(&bytes_image).unwrap().kind,
+        PayloadKind::Bytes
+    );
+    assert!(matches!(
+        extract_meta_from_image(&bytes_image, &options),
+        Err(SecretError::NotMetadata(PayloadKind::Bytes))
+    ));
+}
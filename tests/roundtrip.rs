@@ -0,0 +1,806 @@
+use std::io;
+
+use image::{
+    codecs::gif::{GifDecoder, GifEncoder},
+    imageops::FilterType,
+    AnimationDecoder, ColorType, DynamicImage, Frame, GenericImageView, GrayImage, ImageBuffer,
+    ImageFormat, RgbImage,
+};
+use proptest::prelude::*;
+use secret::{
+    decrypt_image, decrypt_interleaved_image, expand_bits, extract_bit_plane,
+    extract_bytes_from_gif, extract_bytes_from_image, extract_tagged_payload, extract_text_bytes,
+    extract_text_from_image, hide_bytes_across_images, hide_bytes_in_gif, hide_bytes_in_image,
+    hide_image, hide_payload_bytes, hide_tagged_payload, hide_text_bytes, hide_text_in_image,
+    hide_text_in_images, is_lossy_webp, join_bytes_from_images, normalize_image, payload_capacity,
+    progress, read_image_header, read_tagged_payloads, reconstruct, resize_image,
+    resize_image_linear, restore_secret_shape, secret_shape, select_image_bits, strip_lsb,
+    EccStego, EmbedOptions, HideImageOptions, ImageBits, ImageHeader, LsbStego, SecretError,
+    SecretShape, Steganographer,
+};
+
+fn rgb8_image(max_size: u32) -> impl Strategy<Value = DynamicImage> {
+    (1..=max_size, 1..=max_size).prop_flat_map(|(width, height)| {
+        prop::collection::vec(any::<u8>(), (width * height * 3) as usize).prop_map(move |data| {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data).unwrap())
+        })
+    })
+}
+
+fn image_bits() -> impl Strategy<Value = ImageBits> {
+    prop::array::uniform3(1..=4u8).prop_map(ImageBits)
+}
+
+fn carrier() -> impl Strategy<Value = DynamicImage> {
+    let rgb16 = (1..=16u32, 1..=16u32).prop_flat_map(|(width, height)| {
+        prop::collection::vec(any::<u16>(), (width * height * 3) as usize).prop_map(move |data| {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, data).unwrap())
+        })
+    });
+    let luma8 = (1..=16u32, 1..=16u32).prop_flat_map(|(width, height)| {
+        prop::collection::vec(any::<u8>(), (width * height) as usize).prop_map(move |data| {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, data).unwrap())
+        })
+    });
+
+    prop_oneof![rgb8_image(16), rgb16, luma8]
+}
+
+fn embed_options() -> impl Strategy<Value = EmbedOptions> {
+    (prop::option::of(1..=8u8), any::<bool>(), any::<bool>()).prop_map(
+        |(bits, lsb_matching, header)| EmbedOptions {
+            bits,
+            lsb_matching,
+            header,
+            ..EmbedOptions::default()
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn payloads_round_trip_when_they_fit(
+        image in carrier(),
+        payload in prop::collection::vec(any::<u8>(), 0..256),
+        options in embed_options(),
+    ) {
+        let capacity = payload_capacity(&image, &options).unwrap();
+
+        match hide_bytes_in_image(&image, &payload, &options) {
+            Ok(hidden_image) => {
+                prop_assert!(payload.len() <= capacity);
+                prop_assert_eq!(hidden_image.dimensions(), image.dimensions());
+                prop_assert_eq!(extract_bytes_from_image(&hidden_image, &options).unwrap(), payload);
+            }
+            // Carriers without room for the length header report it as needed.
+            Err(SecretError::InsufficientCapacity { needed, available }) if needed > payload.len() => {
+                let length_len = if payload.len() < 0x80 { 1 } else { 2 };
+                prop_assert_eq!(capacity, 0);
+                prop_assert_eq!(needed, payload.len() + length_len);
+                prop_assert!(available < length_len);
+            }
+            Err(SecretError::InsufficientCapacity { needed, available }) => {
+                prop_assert!(payload.len() > capacity);
+                prop_assert_eq!(needed, payload.len());
+                prop_assert_eq!(available, capacity);
+            }
+            Err(error) => prop_assert!(false, "unexpected error: {}", error),
+        }
+    }
+
+    // The header takes 248 red samples with its parity and the ECC stream 2024
+    // more, so every flip lands on a bit that was written, at most 12 of them
+    // in any block of 32 parity bytes.
+    #[test]
+    fn ecc_payloads_survive_flipped_bits(
+        flips in prop::collection::hash_set(0..2272u32, 12),
+    ) {
+        let carrier = DynamicImage::ImageRgb8(RgbImage::from_fn(96, 64, |x, y| {
+            image::Rgb([(x * 3) as u8, (y * 4) as u8, (x ^ y) as u8])
+        }));
+        let payload: Vec<u8> = (0..200).map(|byte| (byte * 7) as u8).collect();
+        let stego = EccStego {
+            parity: 32,
+            lsb: LsbStego {
+                header: true,
+                ..LsbStego::default()
+            },
+        };
+
+        let (hidden_image, report) = stego.embed_with_report(&carrier, &payload).unwrap();
+        prop_assert_eq!(report.pixels_used, 2272);
+        let mut hidden_image = hidden_image.to_rgb8();
+        for &sample in &flips {
+            hidden_image.get_pixel_mut(sample % 96, sample / 96)[0] ^= 1;
+        }
+        let hidden_image = DynamicImage::ImageRgb8(hidden_image);
+
+        prop_assert_eq!(
+            extract_bytes_from_image(&hidden_image, &EmbedOptions::default()).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn hidden_images_keep_the_top_bits_of_the_secret(
+        (source, secret) in (1..=16u32, 1..=16u32).prop_flat_map(|(width, height)| {
+            let image = move || {
+                prop::collection::vec(any::<u8>(), (width * height * 3) as usize)
+                    .prop_map(move |data| RgbImage::from_raw(width, height, data).unwrap())
+            };
+            (image(), image())
+        }),
+        bits in image_bits(),
+    ) {
+        let options = HideImageOptions { bits, ..HideImageOptions::default() };
+        let hidden_image = hide_image(
+            &DynamicImage::ImageRgb8(source),
+            &DynamicImage::ImageRgb8(secret.clone()),
+            &options,
+        ).unwrap();
+        let decrypted = decrypt_image(&hidden_image, bits, 0).unwrap().to_rgb8();
+
+        for (secret_pixel, decrypted_pixel) in secret.pixels().zip(decrypted.pixels()) {
+            for i in 0..3 {
+                let (secret_value, decrypted_value) = (secret_pixel[i], decrypted_pixel[i]);
+                let bits = bits.channel(i);
+
+                prop_assert_eq!(secret_value >> (8 - bits), decrypted_value >> (8 - bits));
+                prop_assert!(secret_value.abs_diff(decrypted_value) < 1 << (8 - bits));
+            }
+        }
+    }
+
+    #[test]
+    fn interleaved_images_keep_twice_the_bits_of_the_secret(
+        (source, secret) in (1..=16u32, 1..=16u32, 0..=1u32).prop_flat_map(|(width, height, odd)| {
+            let image = move |width: u32| {
+                prop::collection::vec(any::<u8>(), (width * height * 3) as usize)
+                    .prop_map(move |data| RgbImage::from_raw(width, height, data).unwrap())
+            };
+            (image(2 * width + odd), image(width))
+        }),
+        bits in image_bits(),
+    ) {
+        let options = HideImageOptions { bits, interleave: true, ..HideImageOptions::default() };
+        let hidden_image = hide_image(
+            &DynamicImage::ImageRgb8(source.clone()),
+            &DynamicImage::ImageRgb8(secret.clone()),
+            &options,
+        ).unwrap();
+        let decrypted = decrypt_interleaved_image(&hidden_image, bits, 0)
+            .unwrap()
+            .to_rgb8();
+
+        prop_assert_eq!(decrypted.dimensions(), secret.dimensions());
+        for (secret_pixel, decrypted_pixel) in secret.pixels().zip(decrypted.pixels()) {
+            for i in 0..3 {
+                let (secret_value, decrypted_value) = (secret_pixel[i], decrypted_pixel[i]);
+                let bits = bits.channel(i);
+
+                prop_assert_eq!(secret_value >> (8 - 2 * bits), decrypted_value >> (8 - 2 * bits));
+            }
+        }
+
+        // No carrier sample changes by more than its `bits` low bits.
+        let hidden_buffer = hidden_image.to_rgb8();
+        for (index, (source_value, hidden_value)) in source.iter().zip(hidden_buffer.iter()).enumerate() {
+            let bits = bits.channel(index % 3);
+            prop_assert_eq!(source_value >> bits, hidden_value >> bits);
+        }
+    }
+
+    #[test]
+    fn hidden_images_keep_the_high_bits_of_the_carrier(
+        (source, secret) in (rgb8_image(16), rgb8_image(16)),
+        bits in image_bits(),
+    ) {
+        let options = HideImageOptions { bits, ..HideImageOptions::default() };
+        let hidden_image = hide_image(&source, &secret, &options).unwrap().to_rgb8();
+
+        let source_buffer = source.to_rgb8();
+        for (index, (source_value, hidden_value)) in source_buffer.iter().zip(hidden_image.iter()).enumerate() {
+            let bits = bits.channel(index % 3);
+            prop_assert_eq!(source_value >> bits, hidden_value >> bits);
+        }
+    }
+}
+
+#[test]
+fn normalization_stretches_each_channel_on_its_own() {
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 1, |x, _| {
+        if x == 0 {
+            image::Rgb([100, 0, 7])
+        } else {
+            image::Rgb([200, 50, 7])
+        }
+    }));
+
+    let normalized = normalize_image(&image).to_rgb8();
+
+    assert_eq!(normalized.get_pixel(0, 0).0, [0, 0, 7]);
+    assert_eq!(normalized.get_pixel(1, 0).0, [255, 255, 7]);
+}
+
+// The mean of the red samples in linear light, from 0 to 1.
+fn mean_linear_light(image: &DynamicImage) -> f64 {
+    let buffer = image.to_rgb8();
+    let total: f64 = buffer
+        .pixels()
+        .map(|pixel| {
+            let value = pixel[0] as f64 / 255.0;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        })
+        .sum();
+
+    total / (buffer.width() * buffer.height()) as f64
+}
+
+#[test]
+fn linear_resizing_keeps_the_brightness_of_fine_details() {
+    // A black and white checkerboard gives off half the light of a white
+    // image, which a downscaled gray should keep.
+    let checkerboard = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+        image::Rgb([if (x + y) % 2 == 0 { 255 } else { 0 }; 3])
+    }));
+
+    let gamma_resized = resize_image(&checkerboard, 16, 16, false, FilterType::Triangle);
+    let linear_resized = resize_image_linear(&checkerboard, 16, 16, false, FilterType::Triangle);
+
+    assert!((mean_linear_light(&checkerboard) - 0.5).abs() < 1e-9);
+    assert!((mean_linear_light(&linear_resized) - 0.5).abs() < 0.01);
+    assert!(mean_linear_light(&gamma_resized) < 0.3);
+}
+
+#[test]
+fn image_headers_keep_the_bits_of_each_channel() {
+    let image = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+
+    for bits in [ImageBits([1, 2, 3]), ImageBits::uniform(4)] {
+        for interleave in [false, true] {
+            let options = HideImageOptions {
+                bits,
+                interleave,
+                header: true,
+                ..HideImageOptions::default()
+            };
+            let hidden_image = hide_image(&image, &image, &options).unwrap();
+
+            assert_eq!(
+                read_image_header(&hidden_image, 0),
+                Some(ImageHeader {
+                    bits,
+                    interleaved: interleave,
+                    // Interleaving halves the room for the secret, which is
+                    // then cropped.
+                    shape: interleave.then_some(SecretShape::Cropped {
+                        width: 8,
+                        height: 8
+                    }),
+                })
+            );
+        }
+    }
+}
+
+// An image whose lowest bits hold `bytes`, MSB-first, and zeros after them.
+fn image_with_low_bits(bytes: &[u8]) -> DynamicImage {
+    let mut buffer = RgbImage::new(16, 16);
+    let bits = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+    for (sample, bit) in buffer.iter_mut().zip(bits) {
+        *sample = bit;
+    }
+    DynamicImage::ImageRgb8(buffer)
+}
+
+fn with_crc(bytes: &[u8]) -> Vec<u8> {
+    [bytes, &crc32fast::hash(bytes).to_be_bytes()].concat()
+}
+
+#[test]
+fn image_headers_need_a_checksum_and_valid_bits() {
+    assert_eq!(
+        read_image_header(&image_with_low_bits(&with_crc(&[0x53, 0x45, 0x02])), 0),
+        Some(ImageHeader {
+            bits: ImageBits::uniform(2),
+            interleaved: false,
+            shape: None,
+        })
+    );
+    assert_eq!(
+        read_image_header(&image_with_low_bits(&[0x53, 0x45, 0x02]), 0),
+        None
+    );
+
+    for bits in [0, 5, 8] {
+        let header = with_crc(&[0x53, 0x45, bits]);
+        assert_eq!(read_image_header(&image_with_low_bits(&header), 0), None);
+    }
+}
+
+#[test]
+fn decrypted_secrets_get_their_original_size_back() {
+    let source = DynamicImage::ImageRgb8(RgbImage::new(48, 32));
+    let secret = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 12, |x, y| {
+        image::Rgb([(x * 12) as u8, (y * 20) as u8, 200])
+    }));
+    let bits = ImageBits::uniform(4);
+
+    for (resize, expand, keep_aspect) in [
+        (true, false, false),
+        (true, false, true),
+        (false, true, false),
+        (false, false, false),
+    ] {
+        let options = HideImageOptions {
+            resize,
+            expand,
+            keep_aspect,
+            bits,
+            header: true,
+            ..HideImageOptions::default()
+        };
+        let hidden_image = hide_image(&source, &secret, &options).unwrap();
+        let header = read_image_header(&hidden_image, 0).unwrap();
+
+        let shape = header.shape.unwrap();
+        assert_eq!(shape.dimensions(), (20, 12));
+        assert_eq!(
+            shape,
+            secret_shape(source.dimensions(), secret.dimensions(), &options).unwrap()
+        );
+        if keep_aspect {
+            assert_eq!(
+                shape,
+                SecretShape::Resized {
+                    width: 20,
+                    height: 12,
+                    inner_width: 48,
+                    inner_height: 29,
+                }
+            );
+        }
+
+        let decrypted = reconstruct(&hidden_image, header.bits, header.interleaved, false).unwrap();
+        let restored = restore_secret_shape(&decrypted, shape, FilterType::Lanczos3).to_rgb8();
+        assert_eq!(restored.dimensions(), (20, 12));
+
+        // Away from the header in the first row, a cropped secret keeps its
+        // top bits exactly and a resized one stays close.
+        let secret_buffer = secret.to_rgb8();
+        for (x, y, pixel) in restored.enumerate_pixels().filter(|&(_, y, _)| y > 0) {
+            for i in 0..3 {
+                let (expected, actual) = (secret_buffer.get_pixel(x, y)[i], pixel[i]);
+                if resize {
+                    assert!(expected.abs_diff(actual) < 48, "{expected} vs {actual}");
+                } else {
+                    assert_eq!(expected >> 4, actual >> 4);
+                }
+            }
+        }
+    }
+
+    // A secret at the carrier's size is hidden as it is.
+    let options = HideImageOptions {
+        resize: true,
+        ..HideImageOptions::default()
+    };
+    assert_eq!(secret_shape((20, 12), (20, 12), &options), None);
+}
+
+#[test]
+fn stripped_images_lose_their_payload() {
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+        image::Rgb([(x * 4) as u8, (y * 4) as u8, 128])
+    }));
+    let payload = b"nothing to see here".repeat(8);
+
+    for bits in [None, Some(2)] {
+        let options = EmbedOptions {
+            bits,
+            ..EmbedOptions::default()
+        };
+        let hidden_image = hide_bytes_in_image(&image, &payload, &options).unwrap();
+        let stripped_image = strip_lsb(&hidden_image, 2);
+
+        assert_ne!(
+            extract_bytes_from_image(&stripped_image, &options).ok(),
+            Some(payload.clone())
+        );
+
+        let (hidden_buffer, stripped_buffer) = (hidden_image.to_rgb8(), stripped_image.to_rgb8());
+        for (hidden_value, stripped_value) in hidden_buffer.iter().zip(stripped_buffer.iter()) {
+            assert_eq!(hidden_value >> 2, stripped_value >> 2);
+        }
+    }
+}
+
+#[test]
+fn tagged_payloads_are_appended_and_read_by_tag() {
+    let stego = LsbStego::default();
+    let image = DynamicImage::ImageRgb8(RgbImage::new(32, 32));
+
+    let (image, _) = hide_tagged_payload(&stego, &image, "note1", b"first note").unwrap();
+    let (image, _) = hide_tagged_payload(&stego, &image, "note2", b"second").unwrap();
+    let (image, _) = hide_tagged_payload(&stego, &image, "note1", b"replaced").unwrap();
+
+    let tagged = read_tagged_payloads(&stego, &image).unwrap();
+    assert_eq!(tagged.tags().collect::<Vec<_>>(), ["note1", "note2"]);
+    assert_eq!(
+        extract_tagged_payload(&stego, &image, "note1").unwrap(),
+        b"replaced"
+    );
+    assert_eq!(
+        extract_tagged_payload(&stego, &image, "note2").unwrap(),
+        b"second"
+    );
+
+    assert!(matches!(
+        extract_tagged_payload(&stego, &image, "note3"),
+        Err(SecretError::TagNotFound(tag)) if tag == "note3"
+    ));
+    assert!(matches!(
+        hide_tagged_payload(&stego, &image, "note3", &[0; 128]),
+        Err(SecretError::InsufficientCapacity { .. })
+    ));
+}
+
+#[test]
+fn progress_counts_the_bytes_and_pixels_processed() {
+    // Other tests run at the same time and add to the shared counters, so
+    // only lower bounds can be checked.
+    let image = DynamicImage::ImageRgb8(RgbImage::new(64, 64));
+    let payload = vec![0x3c; 100];
+    let options = EmbedOptions::default();
+
+    let before = progress();
+    let hidden_image = hide_bytes_in_image(&image, &payload, &options).unwrap();
+    extract_bytes_from_image(&hidden_image, &options).unwrap();
+    decrypt_image(&hidden_image, ImageBits::default(), 0).unwrap();
+    let after = progress();
+
+    // The hidden length and payload, the extracted payload and the pixels.
+    let expected = 101 + 100 + 64 * 64;
+    assert!(after.done - before.done >= expected);
+    assert!(after.total - before.total >= expected);
+    assert!(after.done <= after.total);
+}
+
+#[test]
+fn batches_hide_the_text_in_each_image_on_its_own() {
+    let carrier = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 8) as u8, 64])
+    }));
+    let tiny = DynamicImage::ImageRgb8(RgbImage::new(2, 2));
+    let unreadable = image::ImageError::IoError(io::Error::from(io::ErrorKind::NotFound));
+
+    let images = vec![
+        ("carrier", Ok(carrier)),
+        ("unreadable", Err(unreadable)),
+        ("tiny", Ok(tiny)),
+    ];
+    let results: Vec<_> = hide_text_in_images(images, "batch").collect();
+
+    let keys: Vec<_> = results.iter().map(|(key, _)| *key).collect();
+    assert_eq!(keys, ["carrier", "unreadable", "tiny"]);
+
+    let hidden_image = results[0].1.as_ref().unwrap();
+    assert_eq!(extract_text_from_image(hidden_image).unwrap(), "batch");
+    assert!(matches!(results[1].1, Err(SecretError::Image(_))));
+    assert!(matches!(
+        results[2].1,
+        Err(SecretError::InsufficientCapacity { .. })
+    ));
+}
+
+#[test]
+fn texts_in_encoded_images_round_trip_as_utf8() {
+    let mut carrier = io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 8) as u8, 64])
+    }))
+    .write_to(&mut carrier, ImageFormat::Png)
+    .unwrap();
+    let carrier = carrier.into_inner();
+
+    let stego = hide_text_bytes(&carrier, "héllo wörld", ImageFormat::Png).unwrap();
+    assert_eq!(extract_text_bytes(&stego).unwrap(), "héllo wörld");
+
+    let stego = hide_payload_bytes(&carrier, &[0xFF, 0xFE], ImageFormat::Png).unwrap();
+    assert!(matches!(
+        extract_text_bytes(&stego),
+        Err(SecretError::InvalidText(_))
+    ));
+}
+
+fn split_carriers() -> Vec<DynamicImage> {
+    [(2, 2), (24, 24), (24, 24), (24, 24)]
+        .into_iter()
+        .map(|(width, height)| {
+            DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                image::Rgb([(x * 10) as u8, (y * 10) as u8, 32])
+            }))
+        })
+        .collect()
+}
+
+// The images holding a chunk of the payload.
+fn split_images(carriers: &[DynamicImage], payload: &[u8]) -> Vec<DynamicImage> {
+    hide_bytes_across_images(carriers, payload)
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[test]
+fn split_payloads_skip_carriers_too_small_for_a_chunk() {
+    let carriers = split_carriers();
+    let payload = b"abc";
+
+    let hidden_images = hide_bytes_across_images(&carriers[..2], payload).unwrap();
+
+    assert_eq!(hidden_images.len(), 2);
+    assert!(hidden_images[0].is_none());
+    let hidden_images = [carriers[0].clone(), hidden_images[1].clone().unwrap()];
+    assert_eq!(join_bytes_from_images(&hidden_images).unwrap(), payload);
+}
+
+#[test]
+fn split_payloads_are_joined_past_other_payloads() {
+    let carriers = split_carriers();
+    let payload: Vec<u8> = (0..120).collect();
+    let mut hidden_images = split_images(&carriers, &payload);
+
+    let options = EmbedOptions {
+        header: true,
+        ..EmbedOptions::default()
+    };
+    hidden_images.push(hide_bytes_in_image(&carriers[3], b"not a chunk", &options).unwrap());
+    assert_eq!(join_bytes_from_images(&hidden_images).unwrap(), payload);
+}
+
+#[test]
+fn split_payloads_are_joined_in_any_order() {
+    let payload: Vec<u8> = (0..120).collect();
+    let mut hidden_images = split_images(&split_carriers(), &payload);
+
+    hidden_images.reverse();
+    assert_eq!(join_bytes_from_images(&hidden_images).unwrap(), payload);
+
+    hidden_images.swap(0, 2);
+    assert_eq!(join_bytes_from_images(&hidden_images).unwrap(), payload);
+}
+
+#[test]
+fn split_payloads_with_missing_or_duplicate_chunks_are_refused() {
+    let payload: Vec<u8> = (0..120).collect();
+    let hidden_images = split_images(&split_carriers(), &payload);
+
+    let missing = [&hidden_images[..1], &hidden_images[2..]].concat();
+    assert!(matches!(
+        join_bytes_from_images(&missing),
+        Err(SecretError::MissingChunk(1))
+    ));
+
+    let duplicate = [hidden_images.clone(), vec![hidden_images[2].clone()]].concat();
+    assert!(matches!(
+        join_bytes_from_images(&duplicate),
+        Err(SecretError::InvalidChunk)
+    ));
+}
+
+#[test]
+fn expanded_bits_span_the_full_range() {
+    for bits in 1..=4 {
+        let max_value = (1u8 << bits) - 1;
+        assert_eq!(expand_bits(0, bits), 0);
+        assert_eq!(expand_bits(max_value, bits), 255);
+    }
+    assert_eq!(expand_bits(0, 0), 0);
+}
+
+#[test]
+fn gray_carriers_stay_gray_when_hiding_text() {
+    let carrier = DynamicImage::ImageLuma8(GrayImage::from_fn(32, 24, |x, y| {
+        image::Luma([(x * 8 + y) as u8])
+    }));
+
+    let hidden_image = hide_text_in_image(&carrier, "gray").unwrap();
+    assert_eq!(hidden_image.color(), ColorType::L8);
+    assert_eq!(extract_text_from_image(&hidden_image).unwrap(), "gray");
+
+    let mut png = io::Cursor::new(Vec::new());
+    carrier.write_to(&mut png, ImageFormat::Png).unwrap();
+    let hidden_png = hide_text_bytes(png.get_ref(), "gray", ImageFormat::Png).unwrap();
+    assert_eq!(
+        image::load_from_memory(&hidden_png).unwrap().color(),
+        ColorType::L8
+    );
+}
+
+#[test]
+fn bit_planes_follow_the_bits_of_each_sample() {
+    let gradient = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(256, 1, |x, _| {
+        image::Rgba([x as u8, 255 - x as u8, 0, 255])
+    }));
+
+    for bit in 0..8 {
+        let red_plane = extract_bit_plane(&gradient, 0, bit).unwrap().to_luma8();
+        let green_plane = extract_bit_plane(&gradient, 1, bit).unwrap().to_luma8();
+        for x in 0..256 {
+            let red_set = (x >> bit) & 1 == 1;
+            assert_eq!(red_plane.get_pixel(x, 0)[0], if red_set { 255 } else { 0 });
+            assert_eq!(
+                green_plane.get_pixel(x, 0)[0],
+                if red_set { 0 } else { 255 }
+            );
+        }
+    }
+
+    assert!(matches!(
+        extract_bit_plane(&gradient, 4, 0),
+        Err(SecretError::InvalidChannel(4))
+    ));
+}
+
+#[test]
+fn texts_round_trip_through_bmp_and_lossless_webp() {
+    let mut carrier = io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(RgbImage::from_fn(32, 24, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 10) as u8, (x ^ y) as u8])
+    }))
+    .write_to(&mut carrier, ImageFormat::Png)
+    .unwrap();
+
+    for format in [ImageFormat::Bmp, ImageFormat::WebP] {
+        let hidden = hide_text_bytes(carrier.get_ref(), "lossless", format).unwrap();
+        assert_eq!(image::guess_format(&hidden).unwrap(), format);
+        assert!(!is_lossy_webp(&hidden));
+        assert_eq!(extract_text_bytes(&hidden).unwrap(), "lossless");
+    }
+}
+
+#[test]
+fn texts_are_not_hidden_into_lossy_formats() {
+    let mut carrier = io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(RgbImage::new(32, 24))
+        .write_to(&mut carrier, ImageFormat::Png)
+        .unwrap();
+
+    assert!(matches!(
+        hide_text_bytes(carrier.get_ref(), "hello", ImageFormat::Jpeg),
+        Err(SecretError::LossyFormat(ImageFormat::Jpeg))
+    ));
+}
+
+#[test]
+fn lossy_webps_are_told_apart_from_lossless_ones() {
+    let webp = |chunk: &[u8]| [&b"RIFF\x0c\0\0\0WEBP"[..], chunk, b"\0\0\0\0"].concat();
+
+    assert!(is_lossy_webp(&webp(b"VP8 ")));
+    assert!(!is_lossy_webp(&webp(b"VP8L")));
+    assert!(!is_lossy_webp(b"not a webp"));
+}
+
+// A fully transparent frame, one transparent in its top left quarter and an
+// opaque one, each drawn over the last and with few enough colors to be
+// encoded exactly.
+fn animated_gif() -> Vec<u8> {
+    let frames = (0..3).map(|index| {
+        let buffer = image::RgbaImage::from_fn(48, 32, |x, y| {
+            if index == 0 || (index == 1 && x < 24 && y < 16) {
+                image::Rgba([0; 4])
+            } else {
+                image::Rgba([
+                    (x / 4 * 20) as u8,
+                    (y / 4 * 30) as u8,
+                    (index * 80) as u8,
+                    255,
+                ])
+            }
+        });
+        Frame::new(buffer)
+    });
+
+    let mut gif = Vec::new();
+    GifEncoder::new(&mut gif).encode_frames(frames).unwrap();
+    gif
+}
+
+fn gif_frames(gif: &[u8]) -> Vec<image::RgbaImage> {
+    GifDecoder::new(io::Cursor::new(gif))
+        .unwrap()
+        .into_frames()
+        .map(|frame| frame.unwrap().into_buffer())
+        .collect()
+}
+
+#[test]
+fn gif_payloads_are_spread_across_the_opaque_pixels_of_each_frame() {
+    let gif = animated_gif();
+    // More than the 1152 opaque pixels of the second frame hold.
+    let payload: Vec<u8> = (0..200).map(|byte| (byte * 3) as u8).collect();
+
+    let hidden_gif = hide_bytes_in_gif(&gif, &payload).unwrap();
+    assert_eq!(extract_bytes_from_gif(&hidden_gif).unwrap(), payload);
+
+    let frames = gif_frames(&hidden_gif);
+    assert_eq!(frames.len(), 3);
+    for (frame, hidden_frame) in gif_frames(&gif).iter().zip(&frames) {
+        for (pixel, hidden_pixel) in frame.pixels().zip(hidden_frame.pixels()) {
+            assert_eq!(pixel[3] == 0, hidden_pixel[3] == 0);
+        }
+    }
+}
+
+#[test]
+fn gif_payloads_too_long_for_the_opaque_pixels_are_refused() {
+    let payload = vec![0; 400];
+
+    assert!(matches!(
+        hide_bytes_in_gif(&animated_gif(), &payload),
+        Err(SecretError::InsufficientCapacity { .. })
+    ));
+}
+
+#[test]
+fn bits_are_selected_for_sources_smaller_than_the_secret() {
+    let source = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 30, |x, y| {
+        image::Rgb([(x * 6) as u8, (y * 8) as u8, 128])
+    }));
+    let secret = DynamicImage::ImageRgb8(RgbImage::from_fn(80, 60, |x, y| {
+        image::Rgb([(x ^ y) as u8, (x * 3) as u8, (y * 4) as u8])
+    }));
+
+    for (resize, expand) in [(true, false), (false, true)] {
+        let options = HideImageOptions {
+            resize,
+            expand,
+            ..HideImageOptions::default()
+        };
+
+        let (bits, hidden_psnr) = select_image_bits(&source, &secret, &options, 30.0)
+            .unwrap()
+            .unwrap();
+        assert!((1..=4).contains(&bits));
+        assert!(hidden_psnr >= 30.0);
+    }
+}
+
+#[test]
+fn image_bits_out_of_range_are_refused() {
+    let image = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+
+    for (bits, offset) in [
+        (ImageBits::uniform(0), 0),
+        (ImageBits::uniform(8), 0),
+        (ImageBits([2, 5, 2]), 0),
+        (ImageBits::uniform(4), 6),
+    ] {
+        let options = HideImageOptions {
+            bits,
+            offset,
+            ..HideImageOptions::default()
+        };
+
+        assert!(matches!(
+            hide_image(&image, &image, &options),
+            Err(SecretError::InvalidBits(_))
+        ));
+        assert!(matches!(
+            decrypt_image(&image, bits, offset),
+            Err(SecretError::InvalidBits(_))
+        ));
+        assert!(matches!(
+            decrypt_interleaved_image(&image, bits, offset),
+            Err(SecretError::InvalidBits(_))
+        ));
+    }
+}